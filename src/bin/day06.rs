@@ -1,6 +1,8 @@
 use std::fs::File;
 use std::io::BufRead;
 use std::io::BufReader;
+use std::collections::BTreeSet;
+use std::fmt;
 
 
 type Groups = Vec<Vec<String>>;
@@ -74,6 +76,118 @@ fn count_common_positives(groups: &Groups) -> u32 {
 }
 
 
+const QUESTIONS: std::ops::RangeInclusive<char> = 'a'..='z';
+
+
+#[derive(Debug, PartialEq)]
+struct GroupReport {
+    size: usize,
+    union: BTreeSet<char>,
+    intersection: BTreeSet<char>,
+    unanswered: BTreeSet<char>,
+}
+
+#[derive(Debug)]
+struct Report {
+    groups: Vec<GroupReport>,
+    unanimous_groups: Vec<(char, u32)>,
+}
+
+fn set_to_string(set: &BTreeSet<char>) -> String {
+    set.iter().collect()
+}
+
+impl GroupReport {
+    fn new(group: &[String]) -> GroupReport {
+        let answer_sets: Vec<BTreeSet<char>> = group
+            .iter()
+            .map(|s| s.chars().collect())
+            .collect();
+
+        let union: BTreeSet<char> = answer_sets.iter().flatten().copied().collect();
+        let intersection: BTreeSet<char> = union
+            .iter()
+            .filter(|c| answer_sets.iter().all(|set| set.contains(c)))
+            .copied()
+            .collect();
+        let unanswered: BTreeSet<char> = QUESTIONS
+            .filter(|c| !union.contains(c))
+            .collect();
+
+        GroupReport {
+            size: group.len(),
+            union,
+            intersection,
+            unanswered,
+        }
+    }
+}
+
+impl Report {
+    fn new(groups: &Groups) -> Report {
+        let groups: Vec<GroupReport> = groups
+            .iter()
+            .map(|group| GroupReport::new(group))
+            .collect();
+
+        let unanimous_groups = QUESTIONS
+            .map(|c| {
+                let count = groups
+                    .iter()
+                    .filter(|g| g.intersection.contains(&c))
+                    .count();
+                (c, count as u32)
+            })
+            .collect();
+
+        Report {groups, unanimous_groups}
+    }
+
+    fn groups_csv(&self) -> String {
+        let mut csv = String::from("group,size,union,intersection,unanswered\n");
+        for (i, group) in self.groups.iter().enumerate() {
+            csv.push_str(&format!("{},{},{},{},{}\n",
+                i + 1,
+                group.size,
+                set_to_string(&group.union),
+                set_to_string(&group.intersection),
+                set_to_string(&group.unanswered)));
+        }
+        csv
+    }
+
+    fn questions_csv(&self) -> String {
+        let mut csv = String::from("question,unanimous_groups\n");
+        for (question, count) in &self.unanimous_groups {
+            csv.push_str(&format!("{},{}\n", question, count));
+        }
+        csv
+    }
+}
+
+impl fmt::Display for Report {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        writeln!(f, "{:>5} {:>4}  {:<26}  {:<26}  {:<26}",
+            "Group", "Size", "Union", "Intersection", "Unanswered")?;
+        for (i, group) in self.groups.iter().enumerate() {
+            writeln!(f, "{:>5} {:>4}  {:<26}  {:<26}  {:<26}",
+                i + 1,
+                group.size,
+                set_to_string(&group.union),
+                set_to_string(&group.intersection),
+                set_to_string(&group.unanswered))?;
+        }
+
+        writeln!(f)?;
+        writeln!(f, "Question  Unanimous groups")?;
+        for (question, count) in &self.unanimous_groups {
+            writeln!(f, "{:>8}  {:>16}", question, count)?;
+        }
+        Ok(())
+    }
+}
+
+
 fn main() {
     let filename = "inputs/06.txt";
     let groups = read_groups(filename);
@@ -81,6 +195,15 @@ fn main() {
     // println!("{:?}", groups);
     println!("Part 1: {:?}", count_positives(&groups));
     println!("Part 2: {:?}", count_common_positives(&groups));
+
+    match std::env::args().nth(1).as_deref() {
+        Some("report") => print!("\n{}", Report::new(&groups)),
+        Some("csv") => {
+            let report = Report::new(&groups);
+            print!("\n{}\n{}", report.groups_csv(), report.questions_csv());
+        },
+        _ => {},
+    }
 }
 
 #[cfg(test)]
@@ -104,4 +227,40 @@ mod tests06 {
 
         assert_eq!(positives, 6);
     }
+
+    #[test]
+    fn test03() {
+        let filename = "test_inputs/06_01.txt";
+        let groups = read_groups(filename);
+        let report = Report::new(&groups);
+
+        assert_eq!(report.groups.len(), 5);
+        assert_eq!(report.groups[1].size, 3);
+        assert_eq!(set_to_string(&report.groups[1].union), "abc");
+        assert_eq!(set_to_string(&report.groups[1].intersection), "");
+        assert_eq!(set_to_string(&report.groups[2].intersection), "a");
+        assert_eq!(report.groups[0].unanswered.len(), 23);
+
+        let unanimous_total: u32 = report.unanimous_groups
+            .iter()
+            .map(|(_, count)| count)
+            .sum();
+        assert_eq!(unanimous_total, count_common_positives(&groups));
+        assert_eq!(report.unanimous_groups[0], ('a', 3));
+    }
+
+    #[test]
+    fn test04() {
+        let filename = "test_inputs/06_01.txt";
+        let groups = read_groups(filename);
+        let report = Report::new(&groups);
+
+        let csv = report.groups_csv();
+        let mut lines = csv.lines();
+        assert_eq!(lines.next(), Some("group,size,union,intersection,unanswered"));
+        assert_eq!(lines.next(), Some("1,1,abc,abc,defghijklmnopqrstuvwxyz"));
+
+        let csv = report.questions_csv();
+        assert_eq!(csv.lines().nth(2), Some("b,2"));
+    }
 }
\ No newline at end of file
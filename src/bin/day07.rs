@@ -1,40 +1,221 @@
 use std::fs::File;
 use std::io::{BufReader, BufRead};
 use std::collections::{HashMap, HashSet};
+use std::time::Instant;
+use std::fmt;
 
 
 type BagContents = Vec<(i32, String)>;
 
 
-fn read_line(line: &str) ->
-    Result<(String, BagContents), std::num::ParseIntError>
-{
-    // let len = line.len();
-    let parts: Vec<&str> = line.split(' ').collect();
-    let content_count = (parts.len() - 4) / 4;
-
-    let super_color = format!("{} {}", parts[0], parts[1]);
-    // if content_count == 0 {
-    //     return Ok((super_color, Vec::new()));
-    // }
-
-    let mut bags: Vec<(i32, String)> = Vec::new();
-    for i in 1..=content_count  {
-        let j = i * 4;
-
-        let number: i32 = match parts[j + 0] {
-            "no" => return Ok((super_color, Vec::new())),
-            number => match number.parse() {
-                Ok(number) => number,
-                Err(e) => return Err(e),
-            },
+#[derive(Debug, Clone, Copy, PartialEq)]
+enum ParseRuleErrorKind {
+    MissingColor,
+    MissingBagKeyword,
+    MissingContain,
+    InvalidCount,
+    MissingSeparator,
+    TrailingText,
+}
+
+#[derive(Debug, PartialEq)]
+struct ParseRuleError {
+    kind: ParseRuleErrorKind,
+    text: String,
+}
+
+impl fmt::Display for ParseRuleError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        let message = match self.kind {
+            ParseRuleErrorKind::MissingColor => "Missing bag color",
+            ParseRuleErrorKind::MissingBagKeyword => "Expected \"bag\" or \"bags\"",
+            ParseRuleErrorKind::MissingContain => "Expected \"contain\"",
+            ParseRuleErrorKind::InvalidCount => "Invalid bag count",
+            ParseRuleErrorKind::MissingSeparator => "Expected \",\" or \".\"",
+            ParseRuleErrorKind::TrailingText => "Unexpected text after \".\"",
         };
+        write!(f, "{} in \"{}\"", message, self.text)
+    }
+}
 
-        let color = format!("{} {}", parts[j + 1], parts[j + 2]);
-        bags.push((number, color));
+
+#[derive(Debug, Clone, Copy, PartialEq)]
+enum TokenKind {
+    Word,
+    Comma,
+    Period,
+}
+
+#[derive(Debug, Clone, Copy)]
+struct Token<'a> {
+    kind: TokenKind,
+    text: &'a str,
+    start: usize,
+}
+
+fn tokenize(line: &str) -> Vec<Token<'_>> {
+    let mut tokens = Vec::new();
+    let mut word_start: Option<usize> = None;
+
+    for (i, c) in line.char_indices() {
+        if c.is_whitespace() || c == ',' || c == '.' {
+            if let Some(start) = word_start.take() {
+                tokens.push(Token {
+                    kind: TokenKind::Word, text: &line[start..i], start});
+            }
+        }
+        else if word_start.is_none() {
+            word_start = Some(i);
+        }
+
+        let kind = match c {
+            ',' => TokenKind::Comma,
+            '.' => TokenKind::Period,
+            _ => continue,
+        };
+        tokens.push(Token {kind, text: &line[i..i + 1], start: i});
+    }
+    if let Some(start) = word_start {
+        tokens.push(Token {kind: TokenKind::Word, text: &line[start..], start});
     }
 
-    Ok((super_color, bags))
+    tokens
+}
+
+
+struct RuleParser<'a> {
+    line: &'a str,
+    tokens: Vec<Token<'a>>,
+    position: usize,
+}
+
+impl<'a> RuleParser<'a> {
+    fn new(line: &'a str) -> RuleParser<'a> {
+        RuleParser {
+            line,
+            tokens: tokenize(line),
+            position: 0,
+        }
+    }
+
+    fn peek(&self) -> Option<Token<'a>> {
+        self.tokens.get(self.position).copied()
+    }
+
+    fn peek_word(&self) -> Option<&'a str> {
+        match self.peek() {
+            Some(Token {kind: TokenKind::Word, text, ..}) => Some(text),
+            _ => None,
+        }
+    }
+
+    fn offset(&self) -> usize {
+        match self.peek() {
+            Some(token) => token.start,
+            None => self.line.len(),
+        }
+    }
+
+    /// Text of the clause starting at `start` up to the next separator.
+    fn clause_text(&self, start: usize) -> String {
+        let end = self.tokens[self.position..].iter()
+            .find(|t| t.kind != TokenKind::Word)
+            .map_or(self.line.len(), |t| t.start);
+        let end = end.max(start);
+        String::from(self.line[start..end].trim())
+    }
+
+    fn error(&self, kind: ParseRuleErrorKind, start: usize) -> ParseRuleError {
+        ParseRuleError {kind, text: self.clause_text(start)}
+    }
+
+    /// Reads a color of any number of words, terminated by "bag" or "bags".
+    fn color(&mut self, start: usize) -> Result<String, ParseRuleError> {
+        let mut words: Vec<&str> = Vec::new();
+        loop {
+            match self.peek_word() {
+                Some("bag") | Some("bags") => {
+                    self.position += 1;
+                    break;
+                },
+                Some(word) => {
+                    words.push(word);
+                    self.position += 1;
+                },
+                None => return Err(
+                    self.error(ParseRuleErrorKind::MissingBagKeyword, start)),
+            }
+        }
+
+        if words.is_empty() {
+            return Err(self.error(ParseRuleErrorKind::MissingColor, start));
+        }
+        Ok(words.join(" "))
+    }
+
+    fn clause(&mut self) -> Result<(i32, String), ParseRuleError> {
+        let start = self.offset();
+        let number: i32 = match self.peek_word().map(|w| w.parse()) {
+            Some(Ok(number)) if number > 0 => number,
+            _ => return Err(self.error(ParseRuleErrorKind::InvalidCount, start)),
+        };
+        self.position += 1;
+
+        let color = self.color(start)?;
+        Ok((number, color))
+    }
+
+    fn rule(&mut self) -> Result<(String, BagContents), ParseRuleError> {
+        let super_color = self.color(0)?;
+
+        let start = self.offset();
+        if self.peek_word() != Some("contain") {
+            return Err(self.error(ParseRuleErrorKind::MissingContain, start));
+        }
+        self.position += 1;
+
+        let mut bags: BagContents = Vec::new();
+        if self.peek_word() == Some("no") {
+            let start = self.offset();
+            self.position += 1;
+            for &expected in ["other", "bags"].iter() {
+                if self.peek_word() != Some(expected) {
+                    return Err(self.error(ParseRuleErrorKind::MissingBagKeyword, start));
+                }
+                self.position += 1;
+            }
+        }
+        else {
+            loop {
+                bags.push(self.clause()?);
+                match self.peek() {
+                    Some(Token {kind: TokenKind::Comma, ..}) => self.position += 1,
+                    _ => break,
+                }
+            }
+        }
+
+        match self.peek() {
+            Some(Token {kind: TokenKind::Period, ..}) => self.position += 1,
+            _ => return Err(ParseRuleError {
+                kind: ParseRuleErrorKind::MissingSeparator,
+                text: String::from(self.line.trim()),
+            }),
+        }
+        if let Some(token) = self.peek() {
+            return Err(ParseRuleError {
+                kind: ParseRuleErrorKind::TrailingText,
+                text: String::from(self.line[token.start..].trim()),
+            });
+        }
+
+        Ok((super_color, bags))
+    }
+}
+
+
+fn read_line(line: &str) -> Result<(String, BagContents), ParseRuleError> {
+    RuleParser::new(line).rule()
 }
 
 
@@ -45,12 +226,15 @@ fn read_bags(filename: &str) -> HashMap<String, BagContents> {
     let mut map: HashMap<String, BagContents> = HashMap::new();
     for line in reader.lines() {
         if let Ok(line) = line {
+            if line.trim().is_empty() {
+                continue;
+            }
             match read_line(&line) {
                 Ok((bag, contents)) => {
                     // println!("{:?}: {:?}", &bag, &contents);
                     map.insert(bag, contents);
                 },
-                Err(e) => panic!("{:?}", e),
+                Err(e) => panic!("{}", e),
             }
         }
     }
@@ -104,12 +288,385 @@ fn count_contained_bags(map: &HashMap<String, BagContents>, color: &str)
 }
 
 
+#[derive(Debug, PartialEq)]
+enum RuleErrorKind {
+    UnknownColor { container: String, color: String },
+    Cycle(Vec<String>),
+}
+
+#[derive(Debug, PartialEq)]
+struct RuleError {
+    kind: RuleErrorKind,
+}
+
+impl fmt::Display for RuleError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match &self.kind {
+            RuleErrorKind::UnknownColor { container, color } =>
+                write!(f, "{} bags contain {} bags, which have no rule.",
+                    container, color),
+            RuleErrorKind::Cycle(path) =>
+                write!(f, "Bags contain themselves: {}.", path.join(" -> ")),
+        }
+    }
+}
+
+
+#[derive(Debug, Default)]
+struct RuleCheck {
+    unknown_colors: Vec<(String, String)>,
+    cycles: Vec<Vec<String>>,
+    unreachable: Vec<String>,
+}
+
+impl RuleCheck {
+    fn new(map: &HashMap<String, BagContents>) -> RuleCheck {
+        let mut colors: Vec<&String> = map.keys().collect();
+        colors.sort();
+
+        let mut check = RuleCheck::default();
+        let mut contained: HashSet<&str> = HashSet::new();
+        for &color in &colors {
+            for (_, bag_color) in &map[color] {
+                contained.insert(bag_color);
+                if !map.contains_key(bag_color) {
+                    check.unknown_colors.push((color.clone(), bag_color.clone()));
+                }
+            }
+        }
+
+        check.unreachable = colors.iter()
+            .filter(|color| !contained.contains(color.as_str()))
+            .map(|color| String::from(color.as_str()))
+            .collect();
+
+        let mut finished: HashSet<&str> = HashSet::new();
+        for &color in &colors {
+            let mut path = Vec::new();
+            check.find_cycles(map, color, &mut path, &mut finished);
+        }
+
+        check
+    }
+
+    fn find_cycles<'a>(
+        &mut self,
+        map: &'a HashMap<String, BagContents>,
+        color: &'a str,
+        path: &mut Vec<&'a str>,
+        finished: &mut HashSet<&'a str>,
+    ) {
+        if finished.contains(color) {
+            return;
+        }
+        if let Some(start) = path.iter().position(|&c| c == color) {
+            let mut cycle: Vec<String> = path[start..].iter()
+                .map(|&c| String::from(c))
+                .collect();
+            cycle.push(String::from(color));
+            self.cycles.push(cycle);
+            return;
+        }
+
+        if let Some(bags) = map.get(color) {
+            path.push(color);
+            for (_, bag_color) in bags {
+                self.find_cycles(map, bag_color, path, finished);
+            }
+            path.pop();
+        }
+        finished.insert(color);
+    }
+
+    fn first_error(&self) -> Option<RuleError> {
+        if let Some((container, color)) = self.unknown_colors.first() {
+            return Some(RuleError {
+                kind: RuleErrorKind::UnknownColor {
+                    container: container.clone(),
+                    color: color.clone(),
+                },
+            });
+        }
+        self.cycles.first().map(|cycle| RuleError {
+            kind: RuleErrorKind::Cycle(cycle.clone()),
+        })
+    }
+}
+
+
+struct BagGraph {
+    contents: HashMap<String, BagContents>,
+    parents: HashMap<String, Vec<String>>,
+    totals: HashMap<String, usize>,
+}
+
+impl BagGraph {
+    fn new(contents: HashMap<String, BagContents>) -> Result<BagGraph, RuleError> {
+        let check = RuleCheck::new(&contents);
+        BagGraph::with_check(contents, &check)
+    }
+
+    /// Builds the graph from rules already validated by `check`.
+    fn with_check(contents: HashMap<String, BagContents>, check: &RuleCheck)
+        -> Result<BagGraph, RuleError>
+    {
+        if let Some(e) = check.first_error() {
+            return Err(e);
+        }
+
+        let mut parents: HashMap<String, Vec<String>> = HashMap::new();
+        for (super_color, bags) in &contents {
+            for (_, bag_color) in bags {
+                parents.entry(bag_color.clone())
+                    .or_default()
+                    .push(super_color.clone());
+            }
+        }
+
+        let mut graph = BagGraph {
+            contents,
+            parents,
+            totals: HashMap::new(),
+        };
+
+        let colors: Vec<String> = graph.contents.keys().cloned().collect();
+        let mut totals = HashMap::new();
+        for color in colors {
+            graph.total_contents(&color, &mut totals);
+        }
+        graph.totals = totals;
+
+        Ok(graph)
+    }
+
+    fn total_contents(&self, color: &str, totals: &mut HashMap<String, usize>)
+        -> usize
+    {
+        if let Some(&total) = totals.get(color) {
+            return total;
+        }
+
+        let mut total = 0;
+        if let Some(bags) = self.contents.get(color) {
+            for (bag_count, bag_color) in bags {
+                let bag_count = *bag_count as usize;
+                total += bag_count * (1 + self.total_contents(bag_color, totals));
+            }
+        }
+
+        totals.insert(String::from(color), total);
+        total
+    }
+
+    fn find_containing_bags(&self, color: &str) -> HashSet<String> {
+        let mut containers: HashSet<String> = HashSet::new();
+        let mut pending: Vec<&str> = vec![color];
+
+        while let Some(color) = pending.pop() {
+            if let Some(parents) = self.parents.get(color) {
+                for parent in parents {
+                    if containers.insert(parent.clone()) {
+                        pending.push(parent);
+                    }
+                }
+            }
+        }
+
+        containers
+    }
+
+    fn count_containing_bags(&self, color: &str) -> usize {
+        self.find_containing_bags(color).len()
+    }
+
+    fn count_contained_bags(&self, color: &str) -> usize {
+        self.totals.get(color).copied().unwrap_or(0)
+    }
+}
+
+
+fn benchmark(map: &HashMap<String, BagContents>, color: &str) {
+    // The recursive search does not terminate on cyclic rules, so it only
+    // runs once the graph has validated them.
+    let start = Instant::now();
+    let graph = match BagGraph::new(map.clone()) {
+        Ok(graph) => graph,
+        Err(e) => {
+            println!("Invalid bag rules: {}", e);
+            return;
+        },
+    };
+    let graph_containing = graph.count_containing_bags(color);
+    let graph_contained = graph.count_contained_bags(color);
+    let memoised = start.elapsed();
+
+    let start = Instant::now();
+    let containing = count_containing_bags(map, color);
+    let contained = count_contained_bags(map, color);
+    let naive = start.elapsed();
+
+    assert_eq!(containing, graph_containing);
+    assert_eq!(contained, graph_contained);
+    println!("Recursive search: {:?}", naive);
+    println!("BagGraph (incl. construction): {:?}", memoised);
+}
+
+
+#[derive(Debug, Clone, Copy)]
+enum Reach {
+    Contents,
+    Containers,
+}
+
+/// Collects `color` and all colors reachable from it in the given direction.
+fn reachable_colors(map: &HashMap<String, BagContents>, color: &str, reach: Reach)
+    -> HashSet<String>
+{
+    let mut colors: HashSet<String> = HashSet::new();
+    let mut pending: Vec<String> = vec![String::from(color)];
+
+    while let Some(color) = pending.pop() {
+        if !colors.insert(color.clone()) {
+            continue;
+        }
+        match reach {
+            Reach::Contents => {
+                if let Some(bags) = map.get(&color) {
+                    pending.extend(bags.iter().map(|(_, c)| c.clone()));
+                }
+            },
+            Reach::Containers => {
+                for (super_color, bags) in map {
+                    if bags.iter().any(|(_, c)| *c == color) {
+                        pending.push(super_color.clone());
+                    }
+                }
+            },
+        }
+    }
+
+    colors
+}
+
+/// Sorted edges `(container, count, content)` between the selected colors.
+fn selected_edges<'a>(
+    map: &'a HashMap<String, BagContents>,
+    colors: Option<&HashSet<String>>,
+) -> Vec<(&'a str, i32, &'a str)> {
+    let selected = |color: &str| colors.is_none_or(|c| c.contains(color));
+
+    let mut edges: Vec<(&str, i32, &str)> = map.iter()
+        .filter(|(color, _)| selected(color))
+        .flat_map(|(color, bags)| bags.iter()
+            .filter(|(_, c)| selected(c))
+            .map(move |(count, c)| (color.as_str(), *count, c.as_str())))
+        .collect();
+    edges.sort_by(|a, b| (a.0, a.2).cmp(&(b.0, b.2)));
+    edges
+}
+
+/// Sorted selected colors, including contents without a rule of their own
+/// so that every edge ends in a node.
+fn selected_nodes<'a>(
+    map: &'a HashMap<String, BagContents>,
+    colors: Option<&HashSet<String>>,
+) -> Vec<&'a str> {
+    let mut nodes: Vec<&str> = map.iter()
+        .flat_map(|(color, bags)| std::iter::once(color)
+            .chain(bags.iter().map(|(_, c)| c)))
+        .map(|color| color.as_str())
+        .filter(|color| colors.is_none_or(|c| c.contains(*color)))
+        .collect();
+    nodes.sort();
+    nodes.dedup();
+    nodes
+}
+
+fn quote(s: &str) -> String {
+    format!("\"{}\"", s.replace('\\', "\\\\").replace('"', "\\\""))
+}
+
+fn export_dot(map: &HashMap<String, BagContents>, colors: Option<&HashSet<String>>)
+    -> String
+{
+    let mut dot = String::from("digraph bags {\n");
+    for node in selected_nodes(map, colors) {
+        dot.push_str(&format!("    {};\n", quote(node)));
+    }
+    for (container, count, content) in selected_edges(map, colors) {
+        dot.push_str(&format!("    {} -> {} [label=\"{}\"];\n",
+            quote(container), quote(content), count));
+    }
+    dot.push_str("}\n");
+    dot
+}
+
+fn export_json(map: &HashMap<String, BagContents>, colors: Option<&HashSet<String>>)
+    -> String
+{
+    let edges = selected_edges(map, colors);
+
+    let mut json = String::from("{\n");
+    let nodes = selected_nodes(map, colors);
+    for (i, node) in nodes.iter().enumerate() {
+        let contents: Vec<String> = edges.iter()
+            .filter(|(container, _, _)| container == node)
+            .map(|(_, count, content)| format!(
+                "{{\"color\": {}, \"count\": {}}}", quote(content), count))
+            .collect();
+        json.push_str(&format!("  {}: [{}]", quote(node), contents.join(", ")));
+        json.push_str(if i + 1 < nodes.len() {",\n"} else {"\n"});
+    }
+    json.push_str("}\n");
+    json
+}
+
+
 fn main() {
     let filename = "inputs/07.txt";
     let map = read_bags(filename);
 
-    println!("Part1: {:?}", count_containing_bags(&map, "shiny gold"));
-    println!("Part2: {:?}", count_contained_bags(&map, "shiny gold"));
+    let args: Vec<String> = std::env::args().collect();
+    match args.get(1).map(|s| s.as_str()) {
+        Some("bench") => {
+            benchmark(&map, "shiny gold");
+            return;
+        },
+        Some(format @ "dot") | Some(format @ "json") => {
+            // Usage: day07 dot|json [from|to <color>]
+            let colors = match (args.get(2).map(|s| s.as_str()), args.get(3)) {
+                (Some("from"), Some(color)) =>
+                    Some(reachable_colors(&map, color, Reach::Contents)),
+                (Some("to"), Some(color)) =>
+                    Some(reachable_colors(&map, color, Reach::Containers)),
+                _ => None,
+            };
+            if format == "dot" {
+                print!("{}", export_dot(&map, colors.as_ref()));
+            }
+            else {
+                print!("{}", export_json(&map, colors.as_ref()));
+            }
+            return;
+        },
+        _ => {},
+    }
+
+    let check = RuleCheck::new(&map);
+    if !check.unreachable.is_empty() {
+        println!("Bags not contained in any other bag: {}",
+            check.unreachable.len());
+    }
+
+    let graph = match BagGraph::with_check(map, &check) {
+        Ok(graph) => graph,
+        Err(e) => {
+            println!("Invalid bag rules: {}", e);
+            return;
+        },
+    };
+    println!("Part1: {:?}", graph.count_containing_bags("shiny gold"));
+    println!("Part2: {:?}", graph.count_contained_bags("shiny gold"));
 }
 
 #[cfg(test)]
@@ -139,4 +696,185 @@ mod tests07 {
         let container_count = count_contained_bags(&map, "shiny gold");
         assert_eq!(container_count, 126);
     }
+
+    #[test]
+    fn test04() {
+        for &filename in ["test_inputs/07_01.txt", "test_inputs/07_02.txt"].iter() {
+            let map = read_bags(filename);
+            let graph = BagGraph::new(map.clone()).unwrap();
+
+            assert_eq!(
+                graph.find_containing_bags("shiny gold"),
+                find_containing_bags(&map, "shiny gold"));
+            assert_eq!(
+                graph.count_contained_bags("shiny gold"),
+                count_contained_bags(&map, "shiny gold"));
+        }
+    }
+
+    #[test]
+    fn test05() {
+        let filename = "test_inputs/07_01.txt";
+        let graph = BagGraph::new(read_bags(filename)).unwrap();
+
+        assert_eq!(graph.count_containing_bags("shiny gold"), 4);
+        assert_eq!(graph.count_contained_bags("shiny gold"), 32);
+        assert_eq!(graph.count_contained_bags("faded blue"), 0);
+        assert_eq!(graph.count_containing_bags("light red"), 0);
+    }
+
+    #[test]
+    fn test06() {
+        let filename = "test_inputs/07_01.txt";
+        let check = RuleCheck::new(&read_bags(filename));
+
+        assert!(check.unknown_colors.is_empty());
+        assert!(check.cycles.is_empty());
+        assert_eq!(check.unreachable, vec!["dark orange", "light red"]);
+        assert_eq!(check.first_error(), None);
+    }
+
+    #[test]
+    fn test07() {
+        let filename = "test_inputs/07_03.txt";
+        let map = read_bags(filename);
+        let check = RuleCheck::new(&map);
+
+        assert!(check.unknown_colors.is_empty());
+        assert_eq!(check.cycles, vec![vec![
+            "dark olive", "vibrant plum", "shiny gold", "dark olive"]]);
+        assert_eq!(check.unreachable, vec!["light red"]);
+
+        match BagGraph::new(map.clone()) {
+            Err(RuleError { kind: RuleErrorKind::Cycle(_) }) => (),
+            _ => panic!("Cycle not detected."),
+        }
+
+        match BagGraph::with_check(map, &check) {
+            Err(RuleError { kind: RuleErrorKind::Cycle(path) }) =>
+                assert_eq!(path.len(), 4),
+            _ => panic!("Cycle not detected."),
+        }
+    }
+
+    #[test]
+    fn test08() {
+        let filename = "test_inputs/07_04.txt";
+        let map = read_bags(filename);
+
+        let error = BagGraph::new(map).err().unwrap();
+        assert_eq!(error.kind, RuleErrorKind::UnknownColor {
+            container: String::from("shiny gold"),
+            color: String::from("faded blue"),
+        });
+    }
+
+    #[test]
+    fn test09() {
+        assert_eq!(
+            read_line("light red bags contain 1 bright white bag, 2 muted yellow bags."),
+            Ok((String::from("light red"), vec![
+                (1, String::from("bright white")),
+                (2, String::from("muted yellow")),
+            ])));
+        assert_eq!(
+            read_line("  faded   blue bags  contain no other bags. "),
+            Ok((String::from("faded blue"), Vec::new())));
+        assert_eq!(
+            read_line("dark red bag contain 1 bright shiny gold bag,3 pale bags."),
+            Ok((String::from("dark red"), vec![
+                (1, String::from("bright shiny gold")),
+                (3, String::from("pale")),
+            ])));
+    }
+
+    #[test]
+    fn test10() {
+        for &(line, kind, text) in [
+            ("bags contain no other bags.",
+                ParseRuleErrorKind::MissingColor, "bags contain no other bags"),
+            ("light red bags hold 1 bright white bag.",
+                ParseRuleErrorKind::MissingContain, "hold 1 bright white bag"),
+            ("light red bags contain one bright white bag.",
+                ParseRuleErrorKind::InvalidCount, "one bright white bag"),
+            ("shiny gold bags contain -1 faded blue bag.",
+                ParseRuleErrorKind::InvalidCount, "-1 faded blue bag"),
+            ("shiny gold bags contain 0 faded blue bags.",
+                ParseRuleErrorKind::InvalidCount, "0 faded blue bags"),
+            ("light red bags contain 1 bright white, 2 muted yellow bags.",
+                ParseRuleErrorKind::MissingBagKeyword, "1 bright white"),
+            ("light red bags contain 2 bags.",
+                ParseRuleErrorKind::MissingColor, "2 bags"),
+            ("light red bags contain 1 bright white bag",
+                ParseRuleErrorKind::MissingSeparator,
+                "light red bags contain 1 bright white bag"),
+            ("light red bags contain no other bags. extra",
+                ParseRuleErrorKind::TrailingText, "extra"),
+        ].iter() {
+            assert_eq!(read_line(line), Err(ParseRuleError {
+                kind, text: String::from(text)}));
+        }
+    }
+
+    #[test]
+    fn test11() {
+        let filename = "test_inputs/07_01.txt";
+        let map = read_bags(filename);
+
+        let colors = reachable_colors(&map, "shiny gold", Reach::Contents);
+        let dot = export_dot(&map, Some(&colors));
+        assert_eq!(dot, "digraph bags {
+    \"dark olive\";
+    \"dotted black\";
+    \"faded blue\";
+    \"shiny gold\";
+    \"vibrant plum\";
+    \"dark olive\" -> \"dotted black\" [label=\"4\"];
+    \"dark olive\" -> \"faded blue\" [label=\"3\"];
+    \"shiny gold\" -> \"dark olive\" [label=\"1\"];
+    \"shiny gold\" -> \"vibrant plum\" [label=\"2\"];
+    \"vibrant plum\" -> \"dotted black\" [label=\"6\"];
+    \"vibrant plum\" -> \"faded blue\" [label=\"5\"];
+}
+");
+
+        let dot = export_dot(&map, None);
+        assert_eq!(dot.lines().filter(|l| l.contains("->")).count(), 13);
+    }
+
+    #[test]
+    fn test12() {
+        let filename = "test_inputs/07_01.txt";
+        let map = read_bags(filename);
+
+        let colors = reachable_colors(&map, "shiny gold", Reach::Containers);
+        assert_eq!(colors.len(), 5);
+
+        let json = export_json(&map, Some(&colors));
+        assert_eq!(json, "{
+  \"bright white\": [{\"color\": \"shiny gold\", \"count\": 1}],
+  \"dark orange\": [{\"color\": \"bright white\", \"count\": 3}, {\"color\": \"muted yellow\", \"count\": 4}],
+  \"light red\": [{\"color\": \"bright white\", \"count\": 1}, {\"color\": \"muted yellow\", \"count\": 2}],
+  \"muted yellow\": [{\"color\": \"shiny gold\", \"count\": 2}],
+  \"shiny gold\": []
+}
+");
+    }
+
+
+    #[test]
+    fn test13() {
+        let filename = "test_inputs/07_04.txt";
+        let map = read_bags(filename);
+
+        assert_eq!(export_json(&map, None), "{
+  \"faded blue\": [],
+  \"light red\": [{\"color\": \"shiny gold\", \"count\": 1}],
+  \"shiny gold\": [{\"color\": \"faded blue\", \"count\": 2}]
+}
+");
+        let dot = export_dot(&map, None);
+        assert!(dot.contains("    \"faded blue\";\n"));
+        assert!(dot.contains("    \"shiny gold\" -> \"faded blue\" [label=\"2\"];\n"));
+    }
 }
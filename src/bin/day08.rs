@@ -1,14 +1,64 @@
-use std::fs::File;
 use std::io::BufRead;
-use std::io::BufReader;
-use std::collections::HashSet;
+use std::io::Write;
+use std::collections::{HashMap, HashSet};
+use std::fmt;
 
 
-#[derive(Debug, Clone, Copy)]
+const REGISTER_COUNT: usize = 4;
+
+
+#[allow(clippy::upper_case_acronyms)]
+#[derive(Debug, Clone, Copy, PartialEq)]
 enum Opcode {
     ACC,
     JMP,
-    NOP
+    NOP,
+    JZ,
+    JNZ,
+    STA,
+    LDA,
+    ADR,
+    OUT,
+    HLT,
+}
+
+#[derive(Debug, Clone, Copy, PartialEq)]
+enum OperandKind {
+    None,
+    Value,
+    Offset,
+    Register,
+}
+
+/// Mnemonic and operand of every opcode. Extend this table together with
+/// `Processor::run_instruction` to add new instructions.
+const OPCODES: [(Opcode, &str, OperandKind); 10] = [
+    (Opcode::ACC, "acc", OperandKind::Value),
+    (Opcode::JMP, "jmp", OperandKind::Offset),
+    (Opcode::NOP, "nop", OperandKind::Offset),
+    (Opcode::JZ, "jz", OperandKind::Offset),
+    (Opcode::JNZ, "jnz", OperandKind::Offset),
+    (Opcode::STA, "sta", OperandKind::Register),
+    (Opcode::LDA, "lda", OperandKind::Register),
+    (Opcode::ADR, "adr", OperandKind::Register),
+    (Opcode::OUT, "out", OperandKind::None),
+    (Opcode::HLT, "hlt", OperandKind::None),
+];
+
+impl Opcode {
+    fn from_mnemonic(mnemonic: &str) -> Option<Opcode> {
+        OPCODES.iter()
+            .find(|(_, m, _)| *m == mnemonic)
+            .map(|(opcode, _, _)| *opcode)
+    }
+
+    fn mnemonic(&self) -> &'static str {
+        OPCODES.iter().find(|(o, _, _)| o == self).unwrap().1
+    }
+
+    fn operand(&self) -> OperandKind {
+        OPCODES.iter().find(|(o, _, _)| o == self).unwrap().2
+    }
 }
 
 
@@ -18,73 +68,218 @@ struct Instruction {
     argument: i64,
 }
 
+impl fmt::Display for Instruction {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        let opcode = self.opcode.mnemonic();
+        match self.opcode.operand() {
+            OperandKind::None => write!(f, "{}", opcode),
+            OperandKind::Value | OperandKind::Offset =>
+                write!(f, "{} {:+}", opcode, self.argument),
+            OperandKind::Register => write!(f, "{} r{}", opcode, self.argument),
+        }
+    }
+}
+
 
 type Program = Vec<Instruction>;
 
+/// Opcodes whose control flow never depends on the machine state.
+const ORIGINAL_ISA: [Opcode; 3] = [Opcode::ACC, Opcode::JMP, Opcode::NOP];
+
+fn uses_original_isa(program: &Program) -> bool {
+    program.iter().all(|instruction| ORIGINAL_ISA.contains(&instruction.opcode))
+}
 
+
+/// With `detect_loops` set, executing an instruction a second time is
+/// reported as an infinite loop. This is only correct for programs whose
+/// control flow does not depend on the machine state, so it is off unless
+/// requested with `Processor::detecting_loops`.
 #[derive(Debug)]
 struct Processor {
     accumulator: i64,
     instruction_pointer: i64,
+    registers: [i64; REGISTER_COUNT],
+    output: Vec<i64>,
+    halted: bool,
+    detect_loops: bool,
+    visited: HashSet<i64>,
 }
 
 
-#[derive(Debug)]
+#[derive(Debug, Clone, Copy, PartialEq)]
+enum StepOutcome {
+    Continued,
+    Halted,
+    OutOfBounds(i64),
+    InfiniteLoop(i64),
+}
+
+
+#[derive(Debug, Clone, Copy, PartialEq)]
 enum InstructionErrorKind {
     EmptyInstruction,
     MissingArgument,
     InvalidArgument,
+    UnexpectedArgument,
     UnknownOpcode,
+    UnknownLabel,
+    DuplicateLabel,
 }
 
 
-#[derive(Debug)]
+/// `line` is the zero-based source line the error was found in.
+#[derive(Debug, PartialEq)]
 struct InstructionError {
     kind: InstructionErrorKind,
+    line: usize,
 }
 
 
 impl Instruction {
-    fn read(s: &str) -> Result<Instruction, InstructionError> {
-        let parts: Vec<&str> = s.split(' ').collect();
+    fn parse(
+        s: &str,
+        index: usize,
+        labels: &HashMap<&str, usize>,
+    ) -> Result<Instruction, InstructionErrorKind> {
+        let mut parts = s.split_whitespace();
 
-        let opcode = match parts.get(0) {
-            Some(&v) => v,
-            _ => return Err(InstructionError{
-                kind: InstructionErrorKind::EmptyInstruction})
+        let opcode = match parts.next() {
+            Some(opcode) => opcode,
+            None => return Err(InstructionErrorKind::EmptyInstruction),
         };
-        let opcode = match opcode {
-            "acc" => Opcode::ACC,
-            "jmp" => Opcode::JMP,
-            "nop" => Opcode::NOP,
-            _ => return Err(InstructionError{
-                kind: InstructionErrorKind::UnknownOpcode})
+        let opcode = match Opcode::from_mnemonic(opcode) {
+            Some(opcode) => opcode,
+            None => return Err(InstructionErrorKind::UnknownOpcode),
         };
 
-        let argument = match parts.get(1) {
-            Some(&v) => v,
-            None => return Err(InstructionError{
-                kind: InstructionErrorKind::MissingArgument})
-        };
+        let argument = parts.next();
+        if parts.next().is_some() {
+            return Err(InstructionErrorKind::UnexpectedArgument);
+        }
 
-        let argument: i64 = match argument.parse() {
-            Ok(v) => v,
-            _ => return Err(InstructionError{
-                kind: InstructionErrorKind::InvalidArgument})
+        let argument: i64 = match (opcode.operand(), argument) {
+            (OperandKind::None, None) => 0,
+            (OperandKind::None, Some(_)) =>
+                return Err(InstructionErrorKind::UnexpectedArgument),
+            (_, None) => return Err(InstructionErrorKind::MissingArgument),
+            (OperandKind::Value, Some(v)) => match v.parse() {
+                Ok(v) => v,
+                _ => return Err(InstructionErrorKind::InvalidArgument),
+            },
+            (OperandKind::Offset, Some(v)) => match v.parse() {
+                Ok(v) => v,
+                _ if v.starts_with(|c: char| c.is_alphabetic() || c == '_') => {
+                    match labels.get(v) {
+                        Some(&target) => target as i64 - index as i64,
+                        None => return Err(InstructionErrorKind::UnknownLabel),
+                    }
+                },
+                _ => return Err(InstructionErrorKind::InvalidArgument),
+            },
+            (OperandKind::Register, Some(v)) => {
+                let register = v.strip_prefix('r').map(|r| r.parse::<usize>());
+                match register {
+                    Some(Ok(r)) if r < REGISTER_COUNT => r as i64,
+                    _ => return Err(InstructionErrorKind::InvalidArgument),
+                }
+            },
         };
 
         Ok(Instruction {opcode, argument})
     }
 }
 
-#[derive(Debug)]
-enum ProcessorErrorKind {
-    InvalidInstructionPointer,
+
+/// Strips comments and splits off a leading `label:` of a source line.
+fn split_source_line(line: &str) -> (Option<&str>, &str) {
+    let line = match line.find(['#', ';']) {
+        Some(i) => &line[..i],
+        None => line,
+    };
+    match line.find(':') {
+        Some(i) => (Some(line[..i].trim()), line[i + 1..].trim()),
+        None => (None, line.trim()),
+    }
 }
 
-#[derive(Debug)]
-struct ProcessorError {
-    kind: ProcessorErrorKind,
+/// Assembles source text into a program. Lines may carry a `label:` and
+/// comments starting with `#` or `;`. Jump offsets may name a label.
+fn assemble(source: &str) -> Result<Program, InstructionError> {
+    let mut labels: HashMap<&str, usize> = HashMap::new();
+    let mut index = 0;
+    for (line_number, line) in source.lines().enumerate() {
+        let (label, instruction) = split_source_line(line);
+        if let Some(label) = label {
+            let valid = label.starts_with(|c: char| c.is_alphabetic() || c == '_')
+                && label.chars().all(|c| c.is_alphanumeric() || c == '_');
+            if !valid {
+                return Err(InstructionError {
+                    kind: InstructionErrorKind::InvalidArgument, line: line_number});
+            }
+            if labels.insert(label, index).is_some() {
+                return Err(InstructionError {
+                    kind: InstructionErrorKind::DuplicateLabel, line: line_number});
+            }
+        }
+        if !instruction.is_empty() {
+            index += 1;
+        }
+    }
+
+    let mut program = Program::new();
+    for (line_number, line) in source.lines().enumerate() {
+        let (_, instruction) = split_source_line(line);
+        if instruction.is_empty() {
+            continue;
+        }
+        match Instruction::parse(instruction, program.len(), &labels) {
+            Ok(instruction) => program.push(instruction),
+            Err(kind) => return Err(InstructionError {kind, line: line_number}),
+        }
+    }
+
+    Ok(program)
+}
+
+/// Pretty-prints a program. Jump targets inside the program get labels, so
+/// the output can be assembled again.
+fn disassemble(program: &Program) -> String {
+    let is_jump = |opcode: Opcode| opcode.operand() == OperandKind::Offset
+        && opcode != Opcode::NOP;
+
+    let mut targets: Vec<usize> = program.iter()
+        .enumerate()
+        .filter(|(_, instruction)| is_jump(instruction.opcode))
+        .map(|(i, instruction)| i as i64 + instruction.argument)
+        .filter(|&target| target >= 0 && target <= program.len() as i64)
+        .map(|target| target as usize)
+        .collect();
+    targets.sort_unstable();
+    targets.dedup();
+
+    let mut text = String::new();
+    for (i, instruction) in program.iter().enumerate() {
+        if targets.binary_search(&i).is_ok() {
+            text.push_str(&format!("L{}:\n", i));
+        }
+
+        let target = i as i64 + instruction.argument;
+        let line = if is_jump(instruction.opcode)
+            && targets.binary_search(&(target as usize)).is_ok()
+            && target >= 0
+        {
+            format!("{} L{}", instruction.opcode.mnemonic(), target)
+        } else {
+            format!("{}", instruction)
+        };
+        text.push_str(&format!("    {:<16}# {:4}: {}\n", line, i, instruction));
+    }
+    if targets.binary_search(&program.len()).is_ok() {
+        text.push_str(&format!("L{}:\n", program.len()));
+    }
+
+    text
 }
 
 impl Processor {
@@ -92,197 +287,659 @@ impl Processor {
         Processor {
             accumulator: 0,
             instruction_pointer: 0,
+            registers: [0; REGISTER_COUNT],
+            output: Vec::new(),
+            halted: false,
+            detect_loops: false,
+            visited: HashSet::new(),
+        }
+    }
+
+    fn detecting_loops() -> Processor {
+        Processor {
+            detect_loops: true,
+            ..Processor::new()
         }
     }
 
     fn run_instruction(&mut self, instruction: &Instruction) {
+        let argument = instruction.argument;
+        let jump = match instruction.opcode {
+            Opcode::JMP => true,
+            Opcode::JZ => self.accumulator == 0,
+            Opcode::JNZ => self.accumulator != 0,
+            _ => false,
+        };
         match instruction.opcode {
-            Opcode::ACC => self.accumulator += instruction.argument,
-            Opcode::JMP => {
-                self.instruction_pointer += instruction.argument;
-                self.instruction_pointer -= 1;
+            Opcode::ACC => self.accumulator += argument,
+            Opcode::STA => self.registers[argument as usize] = self.accumulator,
+            Opcode::LDA => self.accumulator = self.registers[argument as usize],
+            Opcode::ADR => self.accumulator += self.registers[argument as usize],
+            Opcode::OUT => self.output.push(self.accumulator),
+            Opcode::HLT => {
+                self.halted = true;
+                return;
             },
-            Opcode::NOP => {},
+            Opcode::JMP | Opcode::JZ | Opcode::JNZ | Opcode::NOP => {},
         };
-        self.instruction_pointer += 1;
+        if jump {
+            self.instruction_pointer += argument;
+        }
+        else {
+            self.instruction_pointer += 1;
+        }
     }
 
-    fn step(&mut self, program: &Program) -> Result<(), ProcessorError> {
-        if self.instruction_pointer < 0
-           || self.instruction_pointer > program.len() as i64
-        {
-            return Err(ProcessorError {
-                kind: ProcessorErrorKind::InvalidInstructionPointer
-            })
+    fn is_halted(&self, program: &Program) -> bool {
+        self.halted || self.instruction_pointer == program.len() as i64
+    }
+
+    /// Executes the next instruction. Returns `Halted` once the program has
+    /// executed `hlt` or run past its last instruction; nothing is executed
+    /// for any outcome other than `Continued` and `Halted`.
+    fn step(&mut self, program: &Program) -> StepOutcome {
+        if self.is_halted(program) {
+            return StepOutcome::Halted;
         }
 
-        let instruction = match program.get(self.instruction_pointer as usize) {
-            Some(instruction) => instruction,
-            None => return Err(ProcessorError {
-                kind: ProcessorErrorKind::InvalidInstructionPointer}),
+        let ip = self.instruction_pointer;
+        let instruction = match program.get(ip as usize) {
+            Some(instruction) if ip >= 0 => instruction,
+            _ => return StepOutcome::OutOfBounds(ip),
         };
+        if self.detect_loops && !self.visited.insert(ip) {
+            return StepOutcome::InfiniteLoop(ip);
+        }
         self.run_instruction(instruction);
 
-        Ok(())
+        if self.is_halted(program) {
+            StepOutcome::Halted
+        }
+        else {
+            StepOutcome::Continued
+        }
+    }
+
+    /// Steps until the program stops or `budget` instructions have been
+    /// executed, in which case `Continued` is returned.
+    fn run(&mut self, program: &Program, budget: Option<usize>) -> StepOutcome {
+        let mut steps = 0;
+        loop {
+            if budget.is_some_and(|budget| steps >= budget) {
+                return StepOutcome::Continued;
+            }
+            match self.step(program) {
+                StepOutcome::Continued => steps += 1,
+                outcome => return outcome,
+            }
+        }
     }
 }
 
 
 fn load_program(filename: &str) -> Result<Program, InstructionError> {
-    let f = File::open(filename).expect("Cannot open file");
-    let reader = BufReader::new(f);
-
-    let mut program = Vec::new();
-    for line in reader.lines() {
-        if let Ok(line) = line {
-            match Instruction::read(&line) {
-                Ok(instruction) => program.push(instruction),
-                Err(e) => return Err(e),
+    let source = std::fs::read_to_string(filename).expect("Cannot open file");
+    assemble(&source)
+}
+
+
+const TRACE_STEP_LIMIT: usize = 1_000_000;
+
+#[derive(Debug, PartialEq)]
+struct TraceStep {
+    instruction_pointer: i64,
+    instruction: String,
+    accumulator_before: i64,
+    accumulator_after: i64,
+}
+
+#[derive(Debug, PartialEq)]
+struct LoopReport {
+    closing_instruction: i64,
+    members: Vec<i64>,
+}
+
+impl LoopReport {
+    fn len(&self) -> usize {
+        self.members.len()
+    }
+}
+
+impl fmt::Display for LoopReport {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        writeln!(f, "Loop of length {} closed by instruction {}.",
+            self.len(), self.closing_instruction)?;
+        let members: Vec<String> = self.members.iter()
+            .map(|ip| ip.to_string())
+            .collect();
+        write!(f, "Members: {}", members.join(" -> "))
+    }
+}
+
+#[derive(Debug)]
+struct Trace {
+    steps: Vec<TraceStep>,
+    outcome: StepOutcome,
+    loop_report: Option<LoopReport>,
+}
+
+impl Trace {
+    /// Runs the program until it halts, it is about to repeat a machine
+    /// state or `TRACE_STEP_LIMIT` steps have been recorded, recording every
+    /// executed step. Programs using only acc/jmp/nop loop as soon as an
+    /// instruction repeats; for all others the accumulator and registers
+    /// have to match as well.
+    fn record(program: &Program) -> Trace {
+        let mut processor = Processor::new();
+        let mut steps: Vec<TraceStep> = Vec::new();
+        let full_state = !uses_original_isa(program);
+        let mut first_step: HashMap<(i64, i64, [i64; REGISTER_COUNT]), usize> =
+            HashMap::new();
+
+        let mut loop_start = None;
+        let mut outcome = StepOutcome::Continued;
+        while outcome == StepOutcome::Continued && steps.len() < TRACE_STEP_LIMIT {
+            if processor.is_halted(program) {
+                outcome = StepOutcome::Halted;
+                break;
+            }
+
+            let ip = processor.instruction_pointer;
+            let state = if full_state {
+                (ip, processor.accumulator, processor.registers)
+            }
+            else {
+                (ip, 0, [0; REGISTER_COUNT])
+            };
+            if let Some(&first) = first_step.get(&state) {
+                outcome = StepOutcome::InfiniteLoop(ip);
+                loop_start = Some(first);
+                break;
+            }
+
+            let accumulator_before = processor.accumulator;
+            outcome = processor.step(program);
+            if let StepOutcome::Continued | StepOutcome::Halted = outcome {
+                first_step.insert(state, steps.len());
+                steps.push(TraceStep {
+                    instruction_pointer: ip,
+                    instruction: program[ip as usize].to_string(),
+                    accumulator_before,
+                    accumulator_after: processor.accumulator,
+                });
             }
         }
+
+        let loop_report = loop_start.map(|first| {
+            let members = steps[first..].iter()
+                .map(|step| step.instruction_pointer)
+                .collect();
+            let closing_instruction = steps.last().unwrap().instruction_pointer;
+            LoopReport {closing_instruction, members}
+        });
+        Trace {steps, outcome, loop_report}
     }
 
-    Ok(program)
+    fn write<W: Write>(&self, output: &mut W) -> std::io::Result<()> {
+        writeln!(output, "step\tip\tinstruction\tacc_before\tacc_after")?;
+        for (i, step) in self.steps.iter().enumerate() {
+            writeln!(output, "{}\t{}\t{}\t{}\t{}", i, step.instruction_pointer,
+                step.instruction, step.accumulator_before, step.accumulator_after)?;
+        }
+        Ok(())
+    }
+
+    fn save(&self, filename: &str) -> std::io::Result<()> {
+        let mut file = std::io::BufWriter::new(std::fs::File::create(filename)?);
+        self.write(&mut file)?;
+        file.flush()
+    }
 }
 
 
 fn part1(filename: &str) -> i64 {
     let program = load_program(filename).expect("Cannot load program.");
 
-    let mut processor = Processor::new();
-    let mut ips: HashSet<i64> = HashSet::new();
+    let mut processor = Processor::detecting_loops();
+    match processor.run(&program, None) {
+        StepOutcome::Halted => println!("Program halted."),
+        StepOutcome::OutOfBounds(ip) =>
+            panic!("Error executing program! Invalid instruction pointer {}", ip),
+        _ => {},
+    }
+
+    processor.accumulator
+}
+
+
+/// Only defined for the original instruction set of acc, jmp and nop.
+fn successor(index: usize, opcode: Opcode, argument: i64) -> i64 {
+    match opcode {
+        Opcode::JMP => index as i64 + argument,
+        _ => index as i64 + 1,
+    }
+}
+
+fn flipped(opcode: Opcode) -> Option<Opcode> {
+    match opcode {
+        Opcode::JMP => Some(Opcode::NOP),
+        Opcode::NOP => Some(Opcode::JMP),
+        _ => None,
+    }
+}
+
 
-    println!("{:?}", processor);
-    while !ips.contains(&processor.instruction_pointer) {
-        ips.insert(processor.instruction_pointer);
-        match processor.step(&program) {
-            Ok(_) => {},
-            Err(e) => panic!("Error executing program! {:?}", e)
+#[derive(Debug, PartialEq)]
+struct Repair {
+    index: usize,
+    original: Opcode,
+    accumulator: i64,
+}
+
+#[derive(Debug, PartialEq)]
+enum RepairErrorKind {
+    UnsupportedInstruction(usize),
+    AlreadyTerminates,
+    NoFix,
+    MultipleFixes(Vec<usize>),
+}
+
+#[derive(Debug, PartialEq)]
+struct RepairError {
+    kind: RepairErrorKind,
+}
+
+/// Marks all instructions from which the unmodified program terminates by
+/// walking the jump graph backwards from the end of the program.
+fn terminating_instructions(program: &Program) -> Vec<bool> {
+    let end = program.len();
+    let mut predecessors: Vec<Vec<usize>> = vec![Vec::new(); end + 1];
+    for (i, instruction) in program.iter().enumerate() {
+        let target = successor(i, instruction.opcode, instruction.argument);
+        if target >= 0 && target <= end as i64 {
+            predecessors[target as usize].push(i);
         }
-        println!("{:?}", processor);
+    }
 
-        if processor.instruction_pointer == program.len() as i64 {
-            println!("Program halted.");
-            break;
+    let mut terminates = vec![false; end + 1];
+    terminates[end] = true;
+    let mut pending = vec![end];
+    while let Some(i) = pending.pop() {
+        for &predecessor in &predecessors[i] {
+            if !terminates[predecessor] {
+                terminates[predecessor] = true;
+                pending.push(predecessor);
+            }
         }
     }
 
-    processor.accumulator
+    terminates
 }
 
+/// Finds the single jmp/nop flip which makes the program terminate.
+fn repair_program(program: &Program) -> Result<Repair, RepairError> {
+    if let Some(index) = program.iter()
+        .position(|instruction| !ORIGINAL_ISA.contains(&instruction.opcode))
+    {
+        return Err(RepairError {
+            kind: RepairErrorKind::UnsupportedInstruction(index)});
+    }
 
-// fn is_looping(program: &Program) -> bool {
-//     is_looping_helper(program, &mut Processor::new())
-// }
+    let end = program.len() as i64;
+    let terminates = terminating_instructions(program);
+    if terminates[0] {
+        return Err(RepairError {kind: RepairErrorKind::AlreadyTerminates});
+    }
 
-fn is_looping_helper(program: &Program, processor: &mut Processor) -> bool {
-    let mut ips: HashSet<i64> = HashSet::new();
+    // Every instruction on the original (looping) path is a candidate.
+    // Flipping it must lead to an instruction that terminates without
+    // further changes.
+    let mut fixes: Vec<usize> = Vec::new();
+    let mut visited = vec![false; program.len()];
+    let mut ip: i64 = 0;
+    while ip >= 0 && ip < end && !visited[ip as usize] {
+        let index = ip as usize;
+        visited[index] = true;
 
-    while !ips.contains(&processor.instruction_pointer) {
-        ips.insert(processor.instruction_pointer);
-        match processor.step(&program) {
-            Ok(_) => {},
-            Err(e) => panic!("Error executing program! {:?}", e)
+        let instruction = &program[index];
+        if let Some(opcode) = flipped(instruction.opcode) {
+            let target = successor(index, opcode, instruction.argument);
+            if target >= 0 && target <= end && terminates[target as usize] {
+                fixes.push(index);
+            }
         }
+        ip = successor(index, instruction.opcode, instruction.argument);
+    }
 
-        if processor.instruction_pointer == program.len() as i64 {
-            return false;
-        }
+    let index = match fixes.as_slice() {
+        [] => return Err(RepairError {kind: RepairErrorKind::NoFix}),
+        &[index] => index,
+        _ => return Err(RepairError {kind: RepairErrorKind::MultipleFixes(fixes)}),
+    };
+
+    let original = program[index].opcode;
+    let replacement = Instruction {
+        opcode: flipped(original).unwrap(),
+        argument: program[index].argument,
+    };
+    let mut processor = Processor::new();
+    while processor.instruction_pointer != end {
+        let ip = processor.instruction_pointer as usize;
+        let instruction = if ip == index {&replacement} else {&program[ip]};
+        processor.run_instruction(instruction);
     }
-    true
+
+    Ok(Repair {index, original, accumulator: processor.accumulator})
 }
 
 
-struct ProgramGenerator {
-    program: Program,
-    current_line: i64,
+fn part2(filename: &str) -> Result<Repair, RepairError> {
+    let program = load_program(filename).expect("Cannot load program.");
+    repair_program(&program)
 }
 
-impl ProgramGenerator {
-    fn new(program: Program) -> Self {
-        ProgramGenerator {
-            program,
-            current_line: 0,
+#[derive(Debug, Clone, Copy, PartialEq)]
+enum Breakpoint {
+    Instruction(i64),
+    Accumulator(i64),
+}
+
+impl fmt::Display for Breakpoint {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match self {
+            Breakpoint::Instruction(ip) => write!(f, "ip == {}", ip),
+            Breakpoint::Accumulator(acc) => write!(f, "acc == {}", acc),
         }
     }
 }
 
-impl Iterator for ProgramGenerator {
-    type Item = Program;
+#[derive(Debug, Clone, Copy, PartialEq)]
+enum Watch {
+    Accumulator,
+    InstructionPointer,
+    CurrentInstruction,
+    Instruction(i64),
+    Register(usize),
+}
 
-    fn next(&mut self) -> Option<Self::Item> {
-        if self.current_line == (self.program.len() as i64) - 1 {
-            return None
+impl Watch {
+    fn parse(words: &[&str]) -> Option<Watch> {
+        match words {
+            ["acc"] => Some(Watch::Accumulator),
+            ["ip"] => Some(Watch::InstructionPointer),
+            ["instr"] => Some(Watch::CurrentInstruction),
+            ["instr", index] => index.parse().ok().map(Watch::Instruction),
+            [register] => match register.strip_prefix('r').map(|r| r.parse()) {
+                Some(Ok(r)) if r < REGISTER_COUNT => Some(Watch::Register(r)),
+                _ => None,
+            },
+            _ => None,
         }
+    }
+}
+
+#[derive(Debug)]
+enum StopReason {
+    Breakpoint(Breakpoint),
+    Halted,
+    Loop,
+    OutOfBounds(i64),
+    Stepped,
+}
 
-        let mut new = Program::new();
-        let mut must_modify = true;
-        for (i, instr) in self.program.iter().enumerate() {
-            if must_modify && i > self.current_line as usize {
-                let opcode = match instr.opcode {
-                    Opcode::JMP => {
-                        must_modify = false;
-                        Opcode::NOP
-                    },
-                    Opcode::NOP => {
-                        must_modify = false;
-                        Opcode::JMP
-                    },
-                    opcode => opcode,
-                };
-                if !must_modify {
-                    self.current_line = i as i64;
-                }
 
+struct Debugger<'a> {
+    program: &'a Program,
+    processor: Processor,
+    breakpoints: Vec<Breakpoint>,
+    watches: Vec<Watch>,
+    visited: HashSet<i64>,
+}
 
-                new.push(Instruction{
-                    opcode,
-                    ..*instr
-                })
+impl<'a> Debugger<'a> {
+    fn new(program: &'a Program) -> Debugger<'a> {
+        Debugger {
+            program,
+            processor: Processor::new(),
+            breakpoints: Vec::new(),
+            watches: Vec::new(),
+            visited: HashSet::new(),
+        }
+    }
+
+    fn step(&mut self) -> StopReason {
+        self.visited.insert(self.processor.instruction_pointer);
+        match self.processor.step(self.program) {
+            StepOutcome::Continued => StopReason::Stepped,
+            StepOutcome::Halted => StopReason::Halted,
+            StepOutcome::OutOfBounds(ip) => StopReason::OutOfBounds(ip),
+            StepOutcome::InfiniteLoop(_) => StopReason::Loop,
+        }
+    }
+
+    /// Accumulator breakpoints only trigger when the accumulator changes.
+    fn hit_breakpoint(&self, last_accumulator: i64) -> Option<Breakpoint> {
+        let accumulator = self.processor.accumulator;
+        self.breakpoints.iter().copied().find(|b| match *b {
+            Breakpoint::Instruction(ip) => self.processor.instruction_pointer == ip,
+            Breakpoint::Accumulator(acc) =>
+                accumulator == acc && last_accumulator != acc,
+        })
+    }
+
+    /// Runs until a breakpoint is hit, the program halts or an instruction
+    /// is about to be executed a second time. The latter is only checked
+    /// for programs using nothing but acc/jmp/nop.
+    fn run_until_break(&mut self) -> StopReason {
+        let detect_loops = uses_original_isa(self.program);
+        loop {
+            let last_accumulator = self.processor.accumulator;
+            match self.step() {
+                StopReason::Stepped => {},
+                reason => return reason,
             }
-            else {
-                new.push(Instruction{
-                    ..*instr
-                })
+            if let Some(breakpoint) = self.hit_breakpoint(last_accumulator) {
+                return StopReason::Breakpoint(breakpoint);
+            }
+            if detect_loops && self.visited.contains(&self.processor.instruction_pointer) {
+                return StopReason::Loop;
             }
         }
-
-        Some(new)
     }
-}
 
+    fn reset(&mut self) {
+        self.processor = Processor::new();
+        self.visited.clear();
+    }
 
+    fn format_watch(&self, watch: &Watch) -> String {
+        let instruction = |ip: i64| match self.program.get(ip as usize) {
+            Some(instruction) if ip >= 0 => format!("{}", instruction),
+            _ => String::from("<none>"),
+        };
+        match watch {
+            Watch::Accumulator =>
+                format!("acc = {}", self.processor.accumulator),
+            Watch::InstructionPointer =>
+                format!("ip = {}", self.processor.instruction_pointer),
+            Watch::CurrentInstruction =>
+                format!("instr = {}", instruction(self.processor.instruction_pointer)),
+            Watch::Instruction(ip) =>
+                format!("instr {} = {}", ip, instruction(*ip)),
+            Watch::Register(r) =>
+                format!("r{} = {}", r, self.processor.registers[*r]),
+        }
+    }
 
-fn part2(filename: &str) -> Option<i64> {
-    let program = load_program(filename).expect("Cannot load program.");
-    let program_generator = ProgramGenerator::new(program);
+    fn report<W: Write>(&self, reason: &StopReason, output: &mut W)
+        -> std::io::Result<()>
+    {
+        match reason {
+            StopReason::Breakpoint(b) => writeln!(output, "Breakpoint: {}", b)?,
+            StopReason::Halted => writeln!(output, "Program halted.")?,
+            StopReason::Loop => writeln!(output,
+                "Instruction {} would be executed twice.",
+                self.processor.instruction_pointer)?,
+            StopReason::OutOfBounds(ip) => writeln!(output,
+                "Error: Invalid instruction pointer {}", ip)?,
+            StopReason::Stepped => {},
+        }
+        self.print_state(output)
+    }
 
-    for program in program_generator {
-        let mut processor = Processor::new();
-        if is_looping_helper(&program, &mut processor) {
-            println!("Program loops!");
+    fn print_state<W: Write>(&self, output: &mut W) -> std::io::Result<()> {
+        writeln!(output, "{} {}",
+            self.format_watch(&Watch::InstructionPointer),
+            self.format_watch(&Watch::Accumulator))?;
+        for watch in &self.watches {
+            writeln!(output, "  {}", self.format_watch(watch))?;
         }
-        else {
-            println!("{:?}", program);
-            return Some(processor.accumulator);
+        Ok(())
+    }
+
+    /// Executes a single debugger command. Returns false on `quit`.
+    fn execute<W: Write>(&mut self, command: &str, output: &mut W)
+        -> std::io::Result<bool>
+    {
+        let words: Vec<&str> = command.split_whitespace().collect();
+        match words.as_slice() {
+            [] => {},
+            ["q"] | ["quit"] => return Ok(false),
+            ["s"] | ["step"] => {
+                let reason = self.step();
+                self.report(&reason, output)?;
+            },
+            ["s", n] | ["step", n] => match n.parse::<usize>() {
+                Ok(n) => {
+                    let mut reason = StopReason::Stepped;
+                    for _ in 0..n {
+                        reason = self.step();
+                        if let StopReason::Stepped = reason {
+                            continue;
+                        }
+                        break;
+                    }
+                    self.report(&reason, output)?;
+                },
+                Err(_) => writeln!(output, "Invalid step count: {}", n)?,
+            },
+            ["c"] | ["continue"] => {
+                let reason = self.run_until_break();
+                self.report(&reason, output)?;
+            },
+            ["b", kind, value] | ["break", kind, value] => {
+                let breakpoint = match (*kind, value.parse()) {
+                    ("ip", Ok(v)) => Some(Breakpoint::Instruction(v)),
+                    ("acc", Ok(v)) => Some(Breakpoint::Accumulator(v)),
+                    _ => None,
+                };
+                match breakpoint {
+                    Some(b) => {
+                        self.breakpoints.push(b);
+                        writeln!(output, "Breakpoint {}: {}", self.breakpoints.len(), b)?;
+                    },
+                    None => writeln!(output, "Usage: break ip|acc <value>")?,
+                }
+            },
+            ["d", n] | ["delete", n] => match n.parse::<usize>() {
+                Ok(n) if n >= 1 && n <= self.breakpoints.len() => {
+                    self.breakpoints.remove(n - 1);
+                },
+                _ => writeln!(output, "No breakpoint {}", n)?,
+            },
+            ["w", expr @ ..] | ["watch", expr @ ..] => match Watch::parse(expr) {
+                Some(watch) => self.watches.push(watch),
+                None => writeln!(output,
+                    "Usage: watch acc|ip|r<n>|instr [<index>]")?,
+            },
+            ["p"] | ["print"] => self.print_state(output)?,
+            ["l"] | ["list"] => {
+                for (i, instruction) in self.program.iter().enumerate() {
+                    let marker = if i as i64 == self.processor.instruction_pointer {
+                        "=>"
+                    } else {
+                        "  "
+                    };
+                    writeln!(output, "{} {:4} {}", marker, i, instruction)?;
+                }
+            },
+            ["r"] | ["reset"] => self.reset(),
+            _ => writeln!(output, "Unknown command: {}", command.trim())?,
         }
+        Ok(true)
     }
 
-    None
+    fn run_prompt<R: BufRead, W: Write>(&mut self, input: R, output: &mut W)
+        -> std::io::Result<()>
+    {
+        write!(output, "(dbg) ")?;
+        output.flush()?;
+        for line in input.lines() {
+            if !self.execute(&line?, output)? {
+                break;
+            }
+            write!(output, "(dbg) ")?;
+            output.flush()?;
+        }
+        Ok(())
+    }
 }
 
 
 fn main() {
+    let args: Vec<String> = std::env::args().collect();
+    if args.get(1).map(|s| s.as_str()) == Some("debug") {
+        let filename = args.get(2).map_or("inputs/08.txt", |s| s.as_str());
+        let program = load_program(filename).expect("Cannot load program.");
+        let stdin = std::io::stdin();
+        let mut stdout = std::io::stdout();
+        Debugger::new(&program)
+            .run_prompt(stdin.lock(), &mut stdout)
+            .expect("Cannot communicate with terminal.");
+        return;
+    }
+    match (args.get(1).map(|s| s.as_str()), args.get(2)) {
+        (Some("disasm"), Some(filename)) => {
+            let program = load_program(filename).expect("Cannot load program.");
+            print!("{}", disassemble(&program));
+            return;
+        },
+        (Some("trace"), Some(filename)) => {
+            // Usage: day08 trace <program> [<trace file>]
+            let program = load_program(filename).expect("Cannot load program.");
+            let trace = Trace::record(&program);
+            match args.get(3) {
+                Some(output) => trace.save(output).expect("Cannot write trace."),
+                None => trace.write(&mut std::io::stdout())
+                    .expect("Cannot write to terminal."),
+            }
+            match &trace.loop_report {
+                Some(report) => println!("{}", report),
+                None => println!("{:?} after {} steps.", trace.outcome, trace.steps.len()),
+            }
+            return;
+        },
+        (Some("run"), Some(filename)) => {
+            let program = load_program(filename).expect("Cannot load program.");
+            let mut processor = Processor::new();
+            let outcome = processor.run(&program, Some(1_000_000));
+            println!("{:?}: accumulator = {}, registers = {:?}",
+                outcome, processor.accumulator, processor.registers);
+            println!("Output: {:?}", processor.output);
+            return;
+        },
+        _ => {},
+    }
+
     let filename = "inputs/08.txt";
 
     let acc = part1(filename);
     println!("Part1: accumulator = {}", acc);
 
-    if let Some(acc) = part2(filename) {
-        println!("Part2: accumulator = {}", acc);
-    }
-    else {
-        println!("Part2: No non-looping program found. :(")
+    match part2(filename) {
+        Ok(repair) => println!("Part2: accumulator = {} (changed {:?} at instruction {})",
+            repair.accumulator, repair.original, repair.index),
+        Err(e) => println!("Part2: No non-looping program found: {:?}", e.kind),
     }
 }
 
@@ -300,7 +957,222 @@ mod tests08 {
     #[test]
     fn test02() {
         let filename = "test_inputs/08_01.txt";
-        let acc = part2(filename);
-        assert_eq!(acc, Some(8));
+        let acc = part2(filename).map(|repair| repair.accumulator);
+        assert_eq!(acc, Ok(8));
+    }
+
+    #[test]
+    fn test03() {
+        let filename = "test_inputs/08_01.txt";
+        let program = load_program(filename).unwrap();
+        let mut debugger = Debugger::new(&program);
+
+        debugger.breakpoints.push(Breakpoint::Instruction(3));
+        match debugger.run_until_break() {
+            StopReason::Breakpoint(Breakpoint::Instruction(3)) => {},
+            reason => panic!("Unexpected stop: {:?}", reason),
+        }
+        assert_eq!(debugger.processor.accumulator, 2);
+
+        debugger.breakpoints.clear();
+        debugger.breakpoints.push(Breakpoint::Accumulator(5));
+        match debugger.run_until_break() {
+            StopReason::Breakpoint(Breakpoint::Accumulator(5)) => {},
+            reason => panic!("Unexpected stop: {:?}", reason),
+        }
+        assert_eq!(debugger.processor.instruction_pointer, 4);
+
+        match debugger.run_until_break() {
+            StopReason::Loop => {},
+            reason => panic!("Unexpected stop: {:?}", reason),
+        }
+        assert_eq!(debugger.processor.instruction_pointer, 1);
+        assert_eq!(debugger.processor.accumulator, 5);
+    }
+
+    #[test]
+    fn test04() {
+        let filename = "test_inputs/08_01.txt";
+        let program = load_program(filename).unwrap();
+        let mut debugger = Debugger::new(&program);
+
+        let input = "watch instr\nbreak ip 3\ncontinue\nstep 2\nfoo\nquit\nstep\n";
+        let mut output: Vec<u8> = Vec::new();
+        debugger.run_prompt(input.as_bytes(), &mut output).unwrap();
+
+        let output = String::from_utf8(output).unwrap();
+        assert_eq!(output, "(dbg) (dbg) Breakpoint 1: ip == 3
+(dbg) Breakpoint: ip == 3
+ip = 3 acc = 2
+  instr = acc +3
+(dbg) ip = 1 acc = 5
+  instr = acc +1
+(dbg) Unknown command: foo
+(dbg) ");
+    }
+
+    #[test]
+    fn test05() {
+        let filename = "test_inputs/08_01.txt";
+        let program = load_program(filename).unwrap();
+        assert_eq!(repair_program(&program), Ok(Repair {
+            index: 7,
+            original: Opcode::JMP,
+            accumulator: 8,
+        }));
+    }
+
+    #[test]
+    fn test06() {
+        let read = |lines: &[&str]| assemble(&lines.join("\n")).unwrap();
+
+        let program = read(&["nop +0", "acc +1"]);
+        assert_eq!(repair_program(&program), Err(RepairError {
+            kind: RepairErrorKind::AlreadyTerminates}));
+
+        let program = read(&["jmp +0", "jmp -1"]);
+        assert_eq!(repair_program(&program), Err(RepairError {
+            kind: RepairErrorKind::NoFix}));
+
+        let program = read(&["nop +3", "jmp -1", "acc +0"]);
+        assert_eq!(repair_program(&program), Err(RepairError {
+            kind: RepairErrorKind::MultipleFixes(vec![0, 1])}));
+    }
+
+    #[test]
+    fn test07() {
+        let filename = "test_inputs/08_02.txt";
+        let program = load_program(filename).unwrap();
+        assert_eq!(program.len(), 11);
+
+        let mut processor = Processor::new();
+        assert_eq!(processor.run(&program, None), StepOutcome::Halted);
+        assert_eq!(processor.output, vec![3, 2, 1, 6]);
+        assert_eq!(processor.instruction_pointer, 10);
+        assert_eq!(processor.registers[..2], [6, 1]);
+    }
+
+    #[test]
+    fn test08() {
+        for &(source, kind, line) in [
+            ("acc +1\nfoo +2", InstructionErrorKind::UnknownOpcode, 1),
+            ("acc", InstructionErrorKind::MissingArgument, 0),
+            ("hlt +1", InstructionErrorKind::UnexpectedArgument, 0),
+            ("acc +1 +2", InstructionErrorKind::UnexpectedArgument, 0),
+            ("sta r9", InstructionErrorKind::InvalidArgument, 0),
+            ("jmp nowhere", InstructionErrorKind::UnknownLabel, 0),
+            ("a: nop +0\n\na: hlt", InstructionErrorKind::DuplicateLabel, 2),
+        ].iter() {
+            let error = assemble(source).err().unwrap();
+            assert_eq!(error, InstructionError {kind, line});
+        }
+    }
+
+    #[test]
+    fn test09() {
+        for &filename in ["test_inputs/08_01.txt", "test_inputs/08_02.txt"].iter() {
+            let program = load_program(filename).unwrap();
+            let text = disassemble(&program);
+            let reassembled = assemble(&text).unwrap();
+
+            let original: Vec<String> = program.iter().map(|i| i.to_string()).collect();
+            let copy: Vec<String> = reassembled.iter().map(|i| i.to_string()).collect();
+            assert_eq!(original, copy);
+        }
+
+        let program = load_program("test_inputs/08_01.txt").unwrap();
+        let text = disassemble(&program);
+        assert_eq!(text.lines().next(), Some("    nop +0          #    0: nop +0"));
+        assert!(text.contains("L6:\n    acc +1          #    6: acc +1\n"));
+        assert!(text.contains("    jmp L6          #    2: jmp +4\n"));
+    }
+
+    #[test]
+    fn test10() {
+        let filename = "test_inputs/08_01.txt";
+        let program = load_program(filename).unwrap();
+        let trace = Trace::record(&program);
+
+        assert_eq!(trace.steps.len(), 7);
+        assert_eq!(trace.outcome, StepOutcome::InfiniteLoop(1));
+        assert_eq!(trace.steps[3], TraceStep {
+            instruction_pointer: 6,
+            instruction: String::from("acc +1"),
+            accumulator_before: 1,
+            accumulator_after: 2,
+        });
+        assert_eq!(trace.steps.last().unwrap().accumulator_after, part1(filename));
+
+        let report = trace.loop_report.unwrap();
+        assert_eq!(report.closing_instruction, 4);
+        assert_eq!(report.members, vec![1, 2, 6, 7, 3, 4]);
+        assert_eq!(report.len(), 6);
+    }
+
+    #[test]
+    fn test11() {
+        let program = assemble("acc +2\nnop +0").unwrap();
+        let trace = Trace::record(&program);
+        assert_eq!(trace.outcome, StepOutcome::Halted);
+        assert_eq!(trace.loop_report, None);
+
+        let mut output: Vec<u8> = Vec::new();
+        trace.write(&mut output).unwrap();
+        assert_eq!(String::from_utf8(output).unwrap(),
+            "step\tip\tinstruction\tacc_before\tacc_after\n\
+             0\t0\tacc +2\t0\t2\n\
+             1\t1\tnop +0\t2\t2\n");
+    }
+
+    #[test]
+    fn test12() {
+        let program = assemble("acc +1\njmp +2\nacc +5\nnop +0").unwrap();
+        let mut processor = Processor::new();
+        assert_eq!(processor.step(&program), StepOutcome::Continued);
+        assert_eq!(processor.run(&program, Some(1)), StepOutcome::Continued);
+        assert_eq!(processor.instruction_pointer, 3);
+        assert_eq!(processor.step(&program), StepOutcome::Halted);
+        assert_eq!(processor.step(&program), StepOutcome::Halted);
+        assert_eq!(processor.accumulator, 1);
+
+        let program = assemble("nop +0\njmp +5").unwrap();
+        let mut processor = Processor::new();
+        assert_eq!(processor.run(&program, None), StepOutcome::OutOfBounds(6));
+
+        let program = assemble("jmp -1").unwrap();
+        let mut processor = Processor::new();
+        assert_eq!(processor.run(&program, None), StepOutcome::OutOfBounds(-1));
+
+        let program = assemble("acc +1\njmp -1").unwrap();
+        let mut processor = Processor::detecting_loops();
+        assert_eq!(processor.run(&program, None), StepOutcome::InfiniteLoop(0));
+        assert_eq!(processor.accumulator, 1);
+
+        let mut processor = Processor::new();
+        assert_eq!(processor.run(&program, Some(9)), StepOutcome::Continued);
+        assert_eq!(processor.accumulator, 5);
     }
-}
\ No newline at end of file
+
+
+    #[test]
+    fn test13() {
+        let program = load_program("test_inputs/08_02.txt").unwrap();
+        let trace = Trace::record(&program);
+        assert_eq!(trace.outcome, StepOutcome::Halted);
+        assert_eq!(trace.loop_report, None);
+        assert_eq!(trace.steps.len(), 25);
+
+        let mut debugger = Debugger::new(&program);
+        match debugger.run_until_break() {
+            StopReason::Halted => {},
+            reason => panic!("Unexpected stop: {:?}", reason),
+        }
+        assert_eq!(debugger.processor.output, vec![3, 2, 1, 6]);
+
+        let program = assemble("acc +1\nsta r0\nacc -1\njz -3").unwrap();
+        let trace = Trace::record(&program);
+        // The first pass changes r0, so the state only repeats at ip 2.
+        assert_eq!(trace.outcome, StepOutcome::InfiniteLoop(2));
+        assert_eq!(trace.loop_report.unwrap().members, vec![2, 3, 0, 1]);
+    }
+}
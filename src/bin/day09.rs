@@ -1,39 +1,151 @@
 use std::fs::File;
 use std::io::BufRead;
 use std::io::BufReader;
+use std::collections::{HashMap, VecDeque};
+use std::ops::Range;
 
 
-fn check_validity(numbers: &Vec<u64>, preamble_length: usize) -> Option<usize> {
-    'outer: for (i, &n) in numbers[preamble_length..].iter().enumerate() {
-        // check all pairs
-        for (j, &a) in numbers[i..i+preamble_length].iter().enumerate() {
-            for (k, &b) in numbers[i..i+preamble_length].iter().enumerate() {
-                if k == j {continue}
-                if a + b == n {
-                    continue 'outer;
+/// Checks numbers one at a time against the sums of all pairs of the
+/// previous `window_length` numbers.
+struct XmasValidator {
+    window_length: usize,
+    window: VecDeque<u64>,
+    pair_sums: HashMap<u64, usize>,
+    index: usize,
+}
+
+impl XmasValidator {
+    fn new(window_length: usize) -> XmasValidator {
+        XmasValidator {
+            window_length,
+            window: VecDeque::with_capacity(window_length),
+            pair_sums: HashMap::new(),
+            index: 0,
+        }
+    }
+
+    fn is_valid(&self, n: u64) -> bool {
+        self.window.len() < self.window_length
+            || self.pair_sums.get(&n).is_some_and(|&count| count > 0)
+    }
+
+    /// Adds the next number and returns whether it is valid.
+    fn push(&mut self, n: u64) -> bool {
+        let valid = self.is_valid(n);
+
+        if self.window.len() == self.window_length {
+            if let Some(oldest) = self.window.pop_front() {
+                for &other in &self.window {
+                    if let Some(sum) = oldest.checked_add(other) {
+                        if let Some(count) = self.pair_sums.get_mut(&sum) {
+                            *count -= 1;
+                            if *count == 0 {
+                                self.pair_sums.remove(&sum);
+                            }
+                        }
+                    }
                 }
             }
         }
-        return Some(i + preamble_length);
+        if self.window_length > 0 {
+            for &other in &self.window {
+                if let Some(sum) = n.checked_add(other) {
+                    *self.pair_sums.entry(sum).or_insert(0) += 1;
+                }
+            }
+            self.window.push_back(n);
+        }
+        self.index += 1;
+
+        valid
     }
-    None
 }
 
 
-fn find_continguous_set(numbers: &Vec<u64>, number: u64) -> &[u64] {
-    for i in 0..=numbers.len() {
-        let mut sum = 0;
-        for (j, n) in numbers[i..].iter().enumerate() {
-            sum += n;
-            if sum > number {
-                break;
+/// Returns index and value of every invalid number read from `reader`.
+fn find_invalid_numbers<R: BufRead>(reader: R, window_length: usize)
+    -> Vec<(usize, u64)>
+{
+    let mut validator = XmasValidator::new(window_length);
+    let mut invalid = Vec::new();
+    for line in reader.lines().map_while(Result::ok) {
+        if let Ok(n) = line.trim().parse() {
+            let index = validator.index;
+            if !validator.push(n) {
+                invalid.push((index, n));
             }
-            else if sum == number {
-                return &numbers[i..=j+i];
+        }
+    }
+    invalid
+}
+
+
+fn check_validity(numbers: &[u64], preamble_length: usize) -> Option<usize> {
+    let mut validator = XmasValidator::new(preamble_length);
+    numbers.iter().position(|&n| !validator.push(n))
+}
+
+
+/// Finds all ranges of at least two numbers summing up to `target` using a
+/// sliding window. Sums exceeding `u64` are treated as too large.
+fn find_contiguous_sets(numbers: &[u64], target: u64) -> Vec<Range<usize>> {
+    let mut ranges = Vec::new();
+    let mut end = 0;
+    let mut sum: u64 = 0;
+
+    for start in 0..numbers.len() {
+        if end < start {
+            end = start;
+            sum = 0;
+        }
+        while end < numbers.len() && sum < target {
+            match sum.checked_add(numbers[end]) {
+                Some(new_sum) => sum = new_sum,
+                None => break,
+            }
+            end += 1;
+        }
+
+        if sum == target {
+            // Trailing zeros extend the range without changing the sum.
+            let mut range_end = end;
+            loop {
+                if range_end - start >= 2 {
+                    ranges.push(start..range_end);
+                }
+                if range_end < numbers.len() && numbers[range_end] == 0 {
+                    range_end += 1;
+                }
+                else {
+                    break;
+                }
             }
         }
+
+        if end > start {
+            sum -= numbers[start];
+        }
+    }
+
+    ranges
+}
+
+#[derive(Debug, Clone, Copy)]
+enum RangeChoice {
+    First,
+    Shortest,
+    Longest,
+}
+
+fn find_contiguous_set(numbers: &[u64], target: u64, choice: RangeChoice)
+    -> Option<Range<usize>>
+{
+    let ranges = find_contiguous_sets(numbers, target).into_iter();
+    match choice {
+        RangeChoice::First => ranges.min_by_key(|r| r.start),
+        RangeChoice::Shortest => ranges.min_by_key(|r| r.len()),
+        RangeChoice::Longest => ranges.max_by_key(|r| r.len()),
     }
-    &numbers[..]
 }
 
 
@@ -53,28 +165,73 @@ fn read_numbers(filename: &str) -> Vec<u64> {
     numbers
 }
 
-fn part2(numbers: &Vec<u64>, preamble_length: usize) -> u64 {
-    let i = check_validity(&numbers, preamble_length).unwrap();
-    let range = find_continguous_set(&numbers, numbers[i]);
+#[derive(Debug, PartialEq)]
+enum XmasErrorKind {
+    NoInvalidNumber,
+    NoContiguousSet,
+}
+
+#[derive(Debug, PartialEq)]
+struct XmasError {
+    kind: XmasErrorKind,
+}
+
+fn part2(numbers: &[u64], preamble_length: usize, choice: RangeChoice)
+    -> Result<u64, XmasError>
+{
+    let i = match check_validity(numbers, preamble_length) {
+        Some(i) => i,
+        None => return Err(XmasError {kind: XmasErrorKind::NoInvalidNumber}),
+    };
+    let range = match find_contiguous_set(numbers, numbers[i], choice) {
+        Some(range) => &numbers[range],
+        None => return Err(XmasError {kind: XmasErrorKind::NoContiguousSet}),
+    };
 
     let min = range.iter().min().unwrap();
     let max = range.iter().max().unwrap();
 
-    min + max
+    Ok(min + max)
 }
 
 
 fn main() {
-    let filename = "inputs/09.txt";
-    let numbers = read_numbers(filename);
+    // Usage: day09 [<window length> [<filename> [first|shortest|longest|all]]]
+    let args: Vec<String> = std::env::args().collect();
+    let preamble_length: usize = match args.get(1) {
+        Some(length) => length.parse().expect("Invalid window length."),
+        None => 25,
+    };
+    let filename = args.get(2).map_or("inputs/09.txt", |s| s.as_str());
 
-    const PREAMBLE_LENGTH: usize = 25;
+    let file = File::open(filename).expect("Cannot open file");
+    for (i, n) in find_invalid_numbers(BufReader::new(file), preamble_length) {
+        println!("Invalid number at {}: {}", i, n);
+    }
 
-    if let Some(i) = check_validity(&numbers, PREAMBLE_LENGTH) {
+    let numbers = read_numbers(filename);
+    if let Some(i) = check_validity(&numbers, preamble_length) {
         println!("Part1: Valid until {}: {}", i, numbers[i]);
     }
 
-    println!("Part2: Sum of min & max: {}", part2(&numbers, PREAMBLE_LENGTH));
+    let choice = match args.get(3).map(|s| s.as_str()) {
+        Some("shortest") => RangeChoice::Shortest,
+        Some("longest") => RangeChoice::Longest,
+        Some("all") => {
+            if let Some(i) = check_validity(&numbers, preamble_length) {
+                for range in find_contiguous_sets(&numbers, numbers[i]) {
+                    println!("Contiguous set: {:?}", range);
+                }
+            }
+            RangeChoice::First
+        },
+        _ => RangeChoice::First,
+    };
+
+    match part2(&numbers, preamble_length, choice) {
+        Ok(sum) => println!("Part2: Sum of min & max: {}", sum),
+        Err(e) => println!("Part2: {:?}", e.kind),
+    }
 }
 
 #[cfg(test)]
@@ -97,6 +254,54 @@ mod tests09 {
         let filename = "test_inputs/09_01.txt";
         let numbers = read_numbers(filename);
 
-        assert_eq!(part2(&numbers, 5), 62);
+        assert_eq!(part2(&numbers, 5, RangeChoice::First), Ok(62));
+    }
+
+    #[test]
+    fn test03() {
+        let filename = "test_inputs/09_01.txt";
+        let file = File::open(filename).unwrap();
+        let invalid = find_invalid_numbers(BufReader::new(file), 5);
+        assert_eq!(invalid, vec![(14, 127)]);
+
+        let numbers = "1\n2\n3\n5\n4\n100\n9\n";
+        let invalid = find_invalid_numbers(numbers.as_bytes(), 2);
+        assert_eq!(invalid, vec![(4, 4), (5, 100), (6, 9)]);
+    }
+
+    #[test]
+    fn test04() {
+        let mut validator = XmasValidator::new(2);
+        assert!(validator.push(u64::MAX));
+        assert!(validator.push(1));
+        assert!(!validator.push(0));
+        assert!(validator.push(1));
+        assert!(validator.push(1));
+        assert!(validator.pair_sums.keys().all(|&sum| sum == 1 || sum == 2));
+    }
+
+    #[test]
+    fn test05() {
+        let filename = "test_inputs/09_01.txt";
+        let numbers = read_numbers(filename);
+
+        assert_eq!(find_contiguous_sets(&numbers, 127), vec![2..6]);
+        assert_eq!(find_contiguous_set(&numbers, 127, RangeChoice::First), Some(2..6));
+        assert_eq!(find_contiguous_set(&numbers, 1, RangeChoice::First), None);
+
+        assert_eq!(part2(&numbers, 25, RangeChoice::First), Err(XmasError {
+            kind: XmasErrorKind::NoInvalidNumber}));
+        assert_eq!(part2(&[1, 2, 4, 50], 2, RangeChoice::First), Err(XmasError {
+            kind: XmasErrorKind::NoContiguousSet}));
+    }
+
+    #[test]
+    fn test06() {
+        let numbers = [3, 2, 1, 0, 5, 1, 1, 4, 6, u64::MAX, 6];
+        assert_eq!(find_contiguous_sets(&numbers, 6),
+            vec![0..3, 0..4, 2..5, 3..6, 4..6, 5..8]);
+        assert_eq!(find_contiguous_set(&numbers, 6, RangeChoice::Shortest), Some(4..6));
+        assert_eq!(find_contiguous_set(&numbers, 6, RangeChoice::Longest), Some(0..4));
+        assert_eq!(find_contiguous_sets(&numbers, u64::MAX), vec![]);
     }
 }
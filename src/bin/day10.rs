@@ -1,6 +1,8 @@
 use std::fs::File;
 use std::io::{BufRead, BufReader};
-use std::collections::HashMap;
+use std::collections::BTreeMap;
+use std::fmt;
+use std::num::NonZeroU64;
 
 
 fn load_adapters(filename: &str) -> Vec<i64> {
@@ -20,78 +22,378 @@ fn load_adapters(filename: &str) -> Vec<i64> {
 }
 
 
-fn part1(adapters: &Vec<i64>) -> u64 {
-    let mut differences: [u64;3] = [0;3];
+/// Describes which adapters can be connected: every step between two
+/// adapters in a chain must be one of `steps`. The outlet has joltage
+/// `outlet`, the device's built-in adapter is `device_offset` jolts higher
+/// than the highest adapter.
+#[derive(Debug, Clone)]
+struct JoltageRules {
+    steps: Vec<i64>,
+    device_offset: i64,
+    outlet: i64,
+}
+
+impl Default for JoltageRules {
+    fn default() -> JoltageRules {
+        JoltageRules {
+            steps: vec![1, 2, 3],
+            device_offset: 3,
+            outlet: 0,
+        }
+    }
+}
+
+impl JoltageRules {
+    fn allows(&self, difference: i64) -> bool {
+        self.steps.contains(&difference)
+    }
+
+    fn max_step(&self) -> i64 {
+        self.steps.iter().copied().max().unwrap_or(0)
+    }
 
-    let mut current = 0;
-    for joltage in adapters {
-        let difference = joltage - current;
-        if difference >= 1 && difference <= 3 {
-            current = *joltage;
+    /// Joltages of outlet, sorted adapters and device.
+    fn chain_nodes(&self, adapters: &[i64]) -> Vec<i64> {
+        let mut nodes: Vec<i64> = adapters.iter()
+            .copied()
+            .filter(|&joltage| joltage > self.outlet)
+            .collect();
+        nodes.sort_unstable();
+
+        let device = nodes.last().copied().unwrap_or(self.outlet) + self.device_offset;
+        nodes.insert(0, self.outlet);
+        nodes.push(device);
+        nodes
+    }
+}
+
+
+#[derive(Debug, PartialEq)]
+enum JoltageErrorKind {
+    InvalidStep { from: i64, to: i64 },
+}
+
+#[derive(Debug, PartialEq)]
+struct JoltageError {
+    kind: JoltageErrorKind,
+}
 
-            differences[(difference - 1) as usize] += 1;
+
+/// Histogram of the differences when using all adapters in a chain.
+fn differences(adapters: &[i64], rules: &JoltageRules)
+    -> Result<BTreeMap<i64, u64>, JoltageError>
+{
+    let nodes = rules.chain_nodes(adapters);
+
+    let mut differences: BTreeMap<i64, u64> = BTreeMap::new();
+    for pair in nodes.windows(2) {
+        let difference = pair[1] - pair[0];
+        if !rules.allows(difference) {
+            return Err(JoltageError {
+                kind: JoltageErrorKind::InvalidStep {from: pair[0], to: pair[1]},
+            });
         }
-        else {
-            panic!("Difference to next adapter it too high: {} - {} = {}",
-                joltage, current, difference);
+        *differences.entry(difference).or_insert(0) += 1;
+    }
+
+    Ok(differences)
+}
+
+
+/// Multiplies the number of smallest and largest allowed steps in a chain
+/// using all adapters.
+fn part1(adapters: &[i64], rules: &JoltageRules) -> Result<u64, JoltageError> {
+    let differences = differences(adapters, rules)?;
+
+    let count = |step: Option<&i64>| step
+        .and_then(|step| differences.get(step))
+        .copied()
+        .unwrap_or(0);
+    Ok(count(rules.steps.iter().min()) * count(rules.steps.iter().max()))
+}
+
+
+/// Number of paths from each node of `nodes` to the last one, combining the
+/// counts of successors with `add`. Returns `None` as soon as `add` fails.
+fn count_paths_to_end<T, F>(nodes: &[i64], rules: &JoltageRules, zero: T, one: T, add: F)
+    -> Option<Vec<T>>
+where
+    T: Clone,
+    F: Fn(&T, &T) -> Option<T>,
+{
+    let max_step = rules.max_step();
+
+    let mut paths = vec![zero.clone(); nodes.len()];
+    if let Some(last) = paths.last_mut() {
+        *last = one;
+    }
+    for i in (0..nodes.len().saturating_sub(1)).rev() {
+        let mut count = zero.clone();
+        for j in (i + 1..nodes.len()).take_while(|&j| nodes[j] - nodes[i] <= max_step) {
+            if rules.allows(nodes[j] - nodes[i]) {
+                count = add(&count, &paths[j])?;
+            }
         }
+        paths[i] = count;
     }
 
-    // current += 3;  // Device's internal adapter.
-    differences[2] += 1;
+    Some(paths)
+}
 
-    for i in 0..differences.len() {
-        println!("{:?} differences of {} joltage", differences[i], i + 1);
+
+/// Unsigned integer of arbitrary size, stored as little-endian 32 bit limbs.
+#[derive(Debug, Clone, PartialEq)]
+struct BigUint(Vec<u32>);
+
+impl BigUint {
+    fn from_u64(n: u64) -> BigUint {
+        let mut limbs = vec![n as u32, (n >> 32) as u32];
+        while limbs.last() == Some(&0) {
+            limbs.pop();
+        }
+        BigUint(limbs)
+    }
+
+    fn add(&self, other: &BigUint) -> BigUint {
+        let mut limbs = Vec::with_capacity(self.0.len().max(other.0.len()) + 1);
+        let mut carry = 0u64;
+        for i in 0..self.0.len().max(other.0.len()) {
+            let a = self.0.get(i).copied().unwrap_or(0) as u64;
+            let b = other.0.get(i).copied().unwrap_or(0) as u64;
+            let sum = a + b + carry;
+            limbs.push(sum as u32);
+            carry = sum >> 32;
+        }
+        if carry > 0 {
+            limbs.push(carry as u32);
+        }
+        BigUint(limbs)
     }
 
-    differences[0] * differences[2]
+    /// Divides in place and returns the remainder.
+    fn div_rem(&mut self, divisor: u32) -> u32 {
+        let mut remainder = 0u64;
+        for limb in self.0.iter_mut().rev() {
+            let current = (remainder << 32) | *limb as u64;
+            *limb = (current / divisor as u64) as u32;
+            remainder = current % divisor as u64;
+        }
+        while self.0.last() == Some(&0) {
+            self.0.pop();
+        }
+        remainder as u32
+    }
 }
 
+impl fmt::Display for BigUint {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        if self.0.is_empty() {
+            return f.write_str("0");
+        }
+
+        let mut n = self.clone();
+        let mut chunks: Vec<u32> = Vec::new();
+        while !n.0.is_empty() {
+            chunks.push(n.div_rem(1_000_000_000));
+        }
 
-/// Counts the number of sub-paths given a list of adapters.
-fn count_paths_away_from(adapters: &[i64]) -> u64 {
-    fn count_paths_from(
-        adapters: &[i64],
-        cache: &mut HashMap<usize, u64>
-    ) -> u64 {
-        if adapters.len() == 1 {
-            return 1
+        let mut chunks = chunks.iter().rev();
+        write!(f, "{}", chunks.next().unwrap())?;
+        for chunk in chunks {
+            write!(f, "{:09}", chunk)?;
         }
-        if cache.contains_key(&adapters.len()) {
-            return cache[&adapters.len()];
+        Ok(())
+    }
+}
+
+
+#[derive(Debug, Clone, Copy)]
+enum CountMode {
+    /// Exact count, switching to `BigUint` if it does not fit into `u64`.
+    Exact,
+    Modulo(NonZeroU64),
+}
+
+#[derive(Debug, Clone, PartialEq)]
+enum ArrangementCount {
+    Exact(u64),
+    Big(BigUint),
+    Modulo(u64, u64),
+}
+
+impl fmt::Display for ArrangementCount {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match self {
+            ArrangementCount::Exact(n) => write!(f, "{}", n),
+            ArrangementCount::Big(n) => write!(f, "{}", n),
+            ArrangementCount::Modulo(n, p) => write!(f, "{} (mod {})", n, p),
         }
+    }
+}
+
+fn count_arrangements(adapters: &[i64], rules: &JoltageRules, mode: CountMode)
+    -> ArrangementCount
+{
+    let nodes = rules.chain_nodes(adapters);
+
+    match mode {
+        CountMode::Exact => {
+            let add = |a: &u64, b: &u64| a.checked_add(*b);
+            if let Some(paths) = count_paths_to_end(&nodes, rules, 0, 1, add) {
+                return ArrangementCount::Exact(paths[0]);
+            }
+
+            let add = |a: &BigUint, b: &BigUint| Some(a.add(b));
+            let zero = BigUint::from_u64(0);
+            let one = BigUint::from_u64(1);
+            let paths = count_paths_to_end(&nodes, rules, zero, one, add).unwrap();
+            ArrangementCount::Big(paths[0].clone())
+        },
+        CountMode::Modulo(p) => {
+            let p = p.get();
+            let add = |a: &u64, b: &u64| Some(((*a as u128 + *b as u128) % p as u128) as u64);
+            let paths = count_paths_to_end(&nodes, rules, 0, 1 % p, add).unwrap();
+            ArrangementCount::Modulo(paths[0], p)
+        },
+    }
+}
+
+
+fn part2(adapters: &[i64], rules: &JoltageRules) -> ArrangementCount {
+    count_arrangements(adapters, rules, CountMode::Exact)
+}
 
-        let mut paths = 0;
 
-        let start = adapters[0];
-        for (i, joltage) in adapters[1..].iter().enumerate() {
-            let difference = joltage - start;
-            if difference <= 3 {
-                paths += count_paths_from(&adapters[i + 1..], cache);
+/// Lazily enumerates all valid chains from the outlet to the device.
+struct Chains {
+    nodes: Vec<i64>,
+    rules: JoltageRules,
+    reaches_end: Vec<bool>,
+    /// Indices of the chain built so far and the next candidate to try
+    /// after each of them.
+    stack: Vec<(usize, usize)>,
+}
+
+impl Chains {
+    fn new(adapters: &[i64], rules: &JoltageRules) -> Chains {
+        let nodes = rules.chain_nodes(adapters);
+        let any = |a: &bool, b: &bool| Some(*a || *b);
+        let reaches_end = count_paths_to_end(&nodes, rules, false, true, any).unwrap();
+        let stack = if reaches_end[0] {vec![(0, 1)]} else {Vec::new()};
+
+        Chains {nodes, rules: rules.clone(), reaches_end, stack}
+    }
+}
+
+impl Iterator for Chains {
+    type Item = Vec<i64>;
+
+    fn next(&mut self) -> Option<Self::Item> {
+        let max_step = self.rules.max_step();
+        let end = self.nodes.len() - 1;
+
+        while let Some(&(node, candidate)) = self.stack.last() {
+            if node == end {
+                let chain = self.stack.iter().map(|&(i, _)| self.nodes[i]).collect();
+                self.stack.pop();
+                return Some(chain);
             }
-            else {
-                break;
+
+            let next = (candidate..self.nodes.len())
+                .take_while(|&j| self.nodes[j] - self.nodes[node] <= max_step)
+                .find(|&j| self.reaches_end[j]
+                    && self.rules.allows(self.nodes[j] - self.nodes[node]));
+            match next {
+                Some(j) => {
+                    self.stack.last_mut().unwrap().1 = j + 1;
+                    self.stack.push((j, j + 1));
+                },
+                None => {
+                    self.stack.pop();
+                },
             }
         }
 
-        cache.insert(adapters.len(), paths);
-        // println!("{:?} paths for {:?}", paths, adapters);
-
-        paths
+        None
     }
+}
 
 
-    let mut cache = HashMap::new();
-    count_paths_from(&adapters, &mut cache)
+#[derive(Debug, PartialEq)]
+struct JoltageReport {
+    differences: BTreeMap<i64, u64>,
+    longest_one_jolt_run: usize,
+    mandatory: Vec<i64>,
+    optional: Vec<i64>,
+}
+
+impl fmt::Display for JoltageReport {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        for (difference, count) in &self.differences {
+            writeln!(f, "{:?} differences of {} joltage", count, difference)?;
+        }
+        writeln!(f, "Longest run of 1 jolt steps: {}", self.longest_one_jolt_run)?;
+        writeln!(f, "Mandatory adapters: {:?}", self.mandatory)?;
+        write!(f, "Optional adapters: {:?}", self.optional)
+    }
 }
 
+/// Analyses the chain using all adapters and determines which adapters are
+/// part of every valid chain.
+fn joltage_report(adapters: &[i64], rules: &JoltageRules)
+    -> Result<JoltageReport, JoltageError>
+{
+    let differences_histogram = differences(adapters, rules)?;
 
-fn part2(adapters: &Vec<i64>) -> u64 {
-    let mut adapters: Vec<i64> = adapters.clone();
-    adapters.insert(0, 0);
-    adapters.push(adapters.iter().max().unwrap() + 3);
+    let nodes = rules.chain_nodes(adapters);
+    let mut longest_one_jolt_run = 0;
+    let mut run = 0;
+    for pair in nodes.windows(2) {
+        run = if pair[1] - pair[0] == 1 {run + 1} else {0};
+        longest_one_jolt_run = longest_one_jolt_run.max(run);
+    }
 
-    count_paths_away_from(&adapters)
+    // An adapter is mandatory unless a connection between two adapters of
+    // some valid chain jumps over it. All nodes are reachable from the
+    // outlet, since the chain using all adapters is valid.
+    let any = |a: &bool, b: &bool| Some(*a || *b);
+    let reaches_end = count_paths_to_end(&nodes, rules, false, true, any).unwrap();
+    let max_step = rules.max_step();
+    let mut jumped_over = vec![0i64; nodes.len() + 1];
+    for i in 0..nodes.len() {
+        if !reaches_end[i] {
+            continue;
+        }
+        for j in (i + 1..nodes.len()).take_while(|&j| nodes[j] - nodes[i] <= max_step) {
+            if reaches_end[j] && rules.allows(nodes[j] - nodes[i]) {
+                jumped_over[i + 1] += 1;
+                jumped_over[j] -= 1;
+            }
+        }
+    }
+
+    let mut mandatory = Vec::new();
+    let mut optional = Vec::new();
+    let mut jumps = 0;
+    for i in 0..nodes.len() {
+        jumps += jumped_over[i];
+        if i == 0 || i == nodes.len() - 1 {
+            continue;
+        }
+        if jumps > 0 {
+            optional.push(nodes[i]);
+        }
+        else {
+            mandatory.push(nodes[i]);
+        }
+    }
+
+    Ok(JoltageReport {
+        differences: differences_histogram,
+        longest_one_jolt_run,
+        mandatory,
+        optional,
+    })
 }
 
 
@@ -102,8 +404,32 @@ fn main() {
 
     adapters.sort();
 
-    println!("Part 1: {}", part1(&adapters));
-    println!("Part 2: {}", part2(&adapters));
+    let rules = JoltageRules::default();
+    match joltage_report(&adapters, &rules) {
+        Ok(report) => println!("{}", report),
+        Err(e) => println!("{:?}", e.kind),
+    }
+    match part1(&adapters, &rules) {
+        Ok(product) => println!("Part 1: {}", product),
+        Err(e) => println!("Part 1: {:?}", e.kind),
+    }
+    println!("Part 2: {}", part2(&adapters, &rules));
+
+    // Usage: day10 chains [<count>] | day10 mod <p>
+    let args: Vec<String> = std::env::args().collect();
+    if args.get(1).map(|s| s.as_str()) == Some("mod") {
+        let p = match args.get(2) {
+            Some(p) => p.parse().expect("Invalid modulus, expected a positive number."),
+            None => NonZeroU64::new(1_000_000_007).unwrap(),
+        };
+        println!("Part 2: {}", count_arrangements(&adapters, &rules, CountMode::Modulo(p)));
+    }
+    if args.get(1).map(|s| s.as_str()) == Some("chains") {
+        let count = args.get(2).map_or(10, |n| n.parse().expect("Invalid count."));
+        for chain in Chains::new(&adapters, &rules).take(count) {
+            println!("{:?}", chain);
+        }
+    }
 }
 
 
@@ -116,8 +442,9 @@ mod tests10 {
         let filename = "test_inputs/10_01.txt";
         let mut adapters = load_adapters(filename);
         adapters.sort();
-        assert_eq!(part1(&adapters), 35);
-        assert_eq!(part2(&adapters), 8);
+        let rules = JoltageRules::default();
+        assert_eq!(part1(&adapters, &rules), Ok(35));
+        assert_eq!(part2(&adapters, &rules), ArrangementCount::Exact(8));
     }
 
     #[test]
@@ -125,7 +452,110 @@ mod tests10 {
         let filename = "test_inputs/10_02.txt";
         let mut adapters = load_adapters(filename);
         adapters.sort();
-        assert_eq!(part1(&adapters), 22 * 10);
-        assert_eq!(part2(&adapters), 19208);
+        let rules = JoltageRules::default();
+        assert_eq!(part1(&adapters, &rules), Ok(22 * 10));
+        assert_eq!(part2(&adapters, &rules), ArrangementCount::Exact(19208));
     }
-}
\ No newline at end of file
+
+    #[test]
+    fn test03() {
+        let filename = "test_inputs/10_01.txt";
+        let adapters = load_adapters(filename);
+        let rules = JoltageRules::default();
+
+        let chains: Vec<Vec<i64>> = Chains::new(&adapters, &rules).collect();
+        assert_eq!(chains.len(), 8);
+        assert_eq!(chains[0], vec![0, 1, 4, 5, 6, 7, 10, 11, 12, 15, 16, 19, 22]);
+        assert_eq!(chains[7], vec![0, 1, 4, 7, 10, 12, 15, 16, 19, 22]);
+
+        let filename = "test_inputs/10_02.txt";
+        let adapters = load_adapters(filename);
+        assert_eq!(Chains::new(&adapters, &rules).count(), 19208);
+    }
+
+    #[test]
+    fn test04() {
+        let adapters = vec![1, 2, 3, 5, 9];
+        let rules = JoltageRules {
+            steps: vec![1, 4],
+            device_offset: 4,
+            outlet: 0,
+        };
+        assert_eq!(part1(&adapters, &rules), Err(JoltageError {
+            kind: JoltageErrorKind::InvalidStep {from: 3, to: 5}}));
+
+        let chains: Vec<Vec<i64>> = Chains::new(&adapters, &rules).collect();
+        assert_eq!(chains, vec![vec![0, 1, 5, 9, 13]]);
+        assert_eq!(part2(&adapters, &rules), ArrangementCount::Exact(1));
+
+        let rules = JoltageRules {
+            steps: vec![2, 5],
+            device_offset: 2,
+            outlet: 1,
+        };
+        assert_eq!(part1(&[3, 8, 10], &rules), Ok(3));
+        assert_eq!(part2(&[3, 8, 10], &rules), ArrangementCount::Exact(1));
+        assert_eq!(part2(&[5], &rules), ArrangementCount::Exact(0));
+        assert_eq!(Chains::new(&[5], &rules).next(), None);
+    }
+
+    #[test]
+    fn test05() {
+        // Consecutive adapters have tribonacci many arrangements.
+        let mut tribonacci: Vec<u128> = vec![0, 0, 1];
+        for i in 3..=122 {
+            tribonacci.push(tribonacci[i - 1] + tribonacci[i - 2] + tribonacci[i - 3]);
+        }
+
+        let rules = JoltageRules::default();
+        let adapters: Vec<i64> = (1..=120).collect();
+        let expected = tribonacci[122];
+        assert!(expected > u64::MAX as u128);
+
+        let count = count_arrangements(&adapters, &rules, CountMode::Exact);
+        match &count {
+            ArrangementCount::Big(n) => assert_eq!(n.to_string(), expected.to_string()),
+            _ => panic!("Expected big integer, got {:?}", count),
+        }
+
+        let p = 1_000_000_007;
+        let mode = CountMode::Modulo(NonZeroU64::new(p).unwrap());
+        assert_eq!(count_arrangements(&adapters, &rules, mode),
+            ArrangementCount::Modulo((expected % p as u128) as u64, p));
+        let mode = CountMode::Modulo(NonZeroU64::new(1).unwrap());
+        assert_eq!(count_arrangements(&adapters, &rules, mode),
+            ArrangementCount::Modulo(0, 1));
+        assert!("0".parse::<NonZeroU64>().is_err());
+
+        let adapters: Vec<i64> = (1..=60).collect();
+        assert_eq!(count_arrangements(&adapters, &rules, CountMode::Exact),
+            ArrangementCount::Exact(tribonacci[62] as u64));
+    }
+
+    #[test]
+    fn test06() {
+        let a = BigUint::from_u64(u64::MAX);
+        let b = a.add(&a).add(&BigUint::from_u64(2));
+        assert_eq!(b.to_string(), "36893488147419103232");
+        assert_eq!(BigUint::from_u64(0).to_string(), "0");
+        assert_eq!(BigUint::from_u64(1_000_000_000).to_string(), "1000000000");
+    }
+
+    #[test]
+    fn test07() {
+        let filename = "test_inputs/10_01.txt";
+        let adapters = load_adapters(filename);
+        let rules = JoltageRules::default();
+
+        let report = joltage_report(&adapters, &rules).unwrap();
+        assert_eq!(report.differences, vec![(1, 7), (3, 5)].into_iter().collect());
+        assert_eq!(report.longest_one_jolt_run, 3);
+        assert_eq!(report.mandatory, vec![1, 4, 7, 10, 12, 15, 16, 19]);
+        assert_eq!(report.optional, vec![5, 6, 11]);
+
+        // Every chain found by enumeration must use all mandatory adapters.
+        for chain in Chains::new(&adapters, &rules) {
+            assert!(report.mandatory.iter().all(|a| chain.contains(a)));
+        }
+    }
+}
@@ -2,6 +2,12 @@ use std::fs::File;
 use std::io::{BufRead, BufReader};
 use std::fmt;
 use std::iter::FromIterator;
+use std::time::Instant;
+use std::collections::HashMap;
+use std::io::{self, Write};
+use std::path::Path;
+use std::thread;
+use std::time::Duration;
 
 #[derive(Debug, Clone, PartialEq)]
 enum TileState {
@@ -13,6 +19,72 @@ enum TileState {
 #[derive(Debug, PartialEq)]
 struct State(Vec<Vec<TileState>>);
 
+#[derive(Debug, Clone, Copy, PartialEq)]
+enum Neighbourhood {
+    Adjacent,
+    Visible,
+}
+
+/// An occupied seat is left when at least `leave_threshold` neighbours are
+/// occupied; an empty seat is taken when at most `sit_threshold` are.
+#[derive(Debug, Clone, PartialEq)]
+struct SeatingRules {
+    neighbourhood: Neighbourhood,
+    leave_threshold: u32,
+    sit_threshold: u32,
+    max_distance: Option<usize>,
+}
+
+impl SeatingRules {
+    fn adjacent() -> SeatingRules {
+        SeatingRules {
+            neighbourhood: Neighbourhood::Adjacent,
+            leave_threshold: 4,
+            sit_threshold: 0,
+            max_distance: None,
+        }
+    }
+
+    fn visible() -> SeatingRules {
+        SeatingRules {
+            neighbourhood: Neighbourhood::Visible,
+            leave_threshold: 5,
+            sit_threshold: 0,
+            max_distance: None,
+        }
+    }
+
+    /// Parses `[adjacent|visible] [leave <n>] [sit <n>] [distance <n>]` in
+    /// any order. Thresholds given explicitly override the defaults of the
+    /// neighbourhood.
+    fn from_args(args: &[String]) -> Option<SeatingRules> {
+        let mut neighbourhood = Neighbourhood::Adjacent;
+        let mut leave_threshold = None;
+        let mut sit_threshold = None;
+        let mut max_distance = None;
+        let mut args = args.iter();
+        while let Some(arg) = args.next() {
+            match arg.as_str() {
+                "adjacent" => neighbourhood = Neighbourhood::Adjacent,
+                "visible" => neighbourhood = Neighbourhood::Visible,
+                "leave" => leave_threshold = Some(args.next()?.parse().ok()?),
+                "sit" => sit_threshold = Some(args.next()?.parse().ok()?),
+                "distance" => max_distance = Some(args.next()?.parse().ok()?),
+                _ => return None,
+            }
+        }
+
+        let mut rules = match neighbourhood {
+            Neighbourhood::Adjacent => SeatingRules::adjacent(),
+            Neighbourhood::Visible => SeatingRules::visible(),
+        };
+        rules.leave_threshold = leave_threshold.unwrap_or(rules.leave_threshold);
+        rules.sit_threshold = sit_threshold.unwrap_or(rules.sit_threshold);
+        rules.max_distance = max_distance;
+        Some(rules)
+    }
+}
+
 impl fmt::Display for State {
     fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
         let state = &self.0;
@@ -69,7 +141,12 @@ impl State {
         count
     }
 
-    fn count_visible_occupied_seats(&self, row: usize, column: usize) -> u32 {
+    fn count_visible_occupied_seats(
+        &self,
+        row: usize,
+        column: usize,
+        max_distance: Option<usize>,
+    ) -> u32 {
         let state = &self.0;
         let rows = state.len();
         if rows == 0 {
@@ -93,7 +170,10 @@ impl State {
                 // println!("  di, dj = {}, {}", di, dj);
                 let mut i = row as i32 + di;
                 let mut j = column as i32 + dj;
-                while 0 <= i && i < rows as i32 && 0 <= j && j < columns as i32 {
+                let mut distance = 1;
+                while 0 <= i && i < rows as i32 && 0 <= j && j < columns as i32
+                    && max_distance.is_none_or(|max| distance <= max)
+                {
                     // println!("    i, j = {:?}, {:?}", i, j);
                     let tile = &state[i as usize][j as usize];
                     match tile {
@@ -110,6 +190,7 @@ impl State {
 
                     i += di;
                     j += dj;
+                    distance += 1;
                 }
             }
         }
@@ -122,37 +203,17 @@ impl State {
         state.iter().flatten().filter(|t| **t == tile).count()
     }
 
-    fn evolve(&self) -> State {
-        let state = &self.0;
-        let mut new: Vec<Vec<TileState>> = Vec::new();
-
-        for (i, row) in state.iter().enumerate() {
-            let mut new_row = Vec::new();
-            for (j, tile) in row.iter().enumerate() {
-                let new_tile = match tile {
-                    TileState::Floor => TileState::Floor,
-                    TileState::Empty => {
-                        match self.count_adjacent_occupied_seats(i, j) {
-                            0 => TileState::Occupied,
-                            _ => TileState::Empty,
-                        }
-                    },
-                    TileState::Occupied => {
-                        match self.count_adjacent_occupied_seats(i, j) {
-                            0..=3 => TileState::Occupied,
-                            4..=u32::MAX => TileState::Empty,
-                        }
-                    },
-                };
-                new_row.push(new_tile);
-            }
-            new.push(new_row);
+    fn count_occupied_neighbours(&self, row: usize, column: usize, rules: &SeatingRules)
+        -> u32
+    {
+        match rules.neighbourhood {
+            Neighbourhood::Adjacent => self.count_adjacent_occupied_seats(row, column),
+            Neighbourhood::Visible =>
+                self.count_visible_occupied_seats(row, column, rules.max_distance),
         }
-
-        State(new)
     }
 
-    fn evolve2(&self) -> State {
+    fn evolve(&self, rules: &SeatingRules) -> State {
         let state = &self.0;
         let mut new: Vec<Vec<TileState>> = Vec::new();
 
@@ -162,15 +223,19 @@ impl State {
                 let new_tile = match tile {
                     TileState::Floor => TileState::Floor,
                     TileState::Empty => {
-                        match self.count_visible_occupied_seats(i, j) {
-                            0 => TileState::Occupied,
-                            _ => TileState::Empty,
+                        if self.count_occupied_neighbours(i, j, rules) <= rules.sit_threshold {
+                            TileState::Occupied
+                        }
+                        else {
+                            TileState::Empty
                         }
                     },
                     TileState::Occupied => {
-                        match self.count_visible_occupied_seats(i, j) {
-                            0..=4 => TileState::Occupied,
-                            5..=u32::MAX => TileState::Empty,
+                        if self.count_occupied_neighbours(i, j, rules) >= rules.leave_threshold {
+                            TileState::Empty
+                        }
+                        else {
+                            TileState::Occupied
                         }
                     },
                 };
@@ -183,7 +248,6 @@ impl State {
     }
 }
 
-
 fn parse_line(line: &str) -> Option<Vec<TileState>> {
     let mut tiles = Vec::new();
     for c in line.chars() {
@@ -219,15 +283,165 @@ fn load_state(filename: &str) -> State {
     State(state)
 }
 
-fn part1(start_state: State) -> usize {
+/// Neighbours of every seat, computed once for a floor layout. Floor tiles
+/// never change, so the neighbours stay valid across generations.
+struct SeatGraph {
+    shape: (usize, usize),
+    seats: Vec<(usize, usize)>,
+    neighbours: Vec<Vec<usize>>,
+}
+
+impl SeatGraph {
+    fn new(state: &State, rules: &SeatingRules) -> SeatGraph {
+        let tiles = &state.0;
+        let rows = tiles.len();
+        let columns = tiles.first().map_or(0, |row| row.len());
+
+        let mut index = vec![vec![None; columns]; rows];
+        let mut seats = Vec::new();
+        for (i, row) in tiles.iter().enumerate() {
+            for (j, tile) in row.iter().enumerate() {
+                if *tile != TileState::Floor {
+                    index[i][j] = Some(seats.len());
+                    seats.push((i, j));
+                }
+            }
+        }
+
+        let max_distance = match rules.neighbourhood {
+            Neighbourhood::Adjacent => Some(1),
+            Neighbourhood::Visible => rules.max_distance,
+        };
+
+        let mut neighbours = Vec::with_capacity(seats.len());
+        for &(row, column) in &seats {
+            let mut seat_neighbours = Vec::new();
+            for &di in &[-1i32, 0, 1] {
+                for &dj in &[-1i32, 0, 1] {
+                    if di == 0 && dj == 0 {
+                        continue;
+                    }
+                    let mut i = row as i32 + di;
+                    let mut j = column as i32 + dj;
+                    let mut distance = 1;
+                    while 0 <= i && i < rows as i32 && 0 <= j && j < columns as i32
+                        && max_distance.is_none_or(|max| distance <= max)
+                    {
+                        if let Some(seat) = index[i as usize][j as usize] {
+                            seat_neighbours.push(seat);
+                            break;
+                        }
+                        i += di;
+                        j += dj;
+                        distance += 1;
+                    }
+                }
+            }
+            neighbours.push(seat_neighbours);
+        }
+
+        SeatGraph {
+            shape: (rows, columns),
+            seats,
+            neighbours,
+        }
+    }
+}
+
+
+/// Evolves seats on a `SeatGraph`, only re-evaluating seats whose
+/// neighbours changed in the previous round.
+struct SeatSimulation<'a> {
+    graph: &'a SeatGraph,
+    rules: SeatingRules,
+    occupied: Vec<bool>,
+    occupied_neighbours: Vec<u32>,
+    pending: Vec<usize>,
+}
+
+impl<'a> SeatSimulation<'a> {
+    fn new(graph: &'a SeatGraph, state: &State, rules: &SeatingRules) -> SeatSimulation<'a> {
+        let occupied: Vec<bool> = graph.seats.iter()
+            .map(|&(i, j)| state.0[i][j] == TileState::Occupied)
+            .collect();
+        let occupied_neighbours = graph.neighbours.iter()
+            .map(|neighbours| neighbours.iter().filter(|&&n| occupied[n]).count() as u32)
+            .collect();
+
+        SeatSimulation {
+            graph,
+            rules: rules.clone(),
+            occupied,
+            occupied_neighbours,
+            pending: (0..graph.seats.len()).collect(),
+        }
+    }
+
+    /// Runs one round and returns the number of seats which changed.
+    fn step(&mut self) -> usize {
+        let changed: Vec<usize> = self.pending.iter()
+            .copied()
+            .filter(|&seat| {
+                let count = self.occupied_neighbours[seat];
+                if self.occupied[seat] {
+                    count >= self.rules.leave_threshold
+                }
+                else {
+                    count <= self.rules.sit_threshold
+                }
+            })
+            .collect();
+
+        let mut is_pending = vec![false; self.occupied.len()];
+        let mut pending = Vec::new();
+        for &seat in &changed {
+            self.occupied[seat] = !self.occupied[seat];
+            for &neighbour in &self.graph.neighbours[seat] {
+                if self.occupied[seat] {
+                    self.occupied_neighbours[neighbour] += 1;
+                }
+                else {
+                    self.occupied_neighbours[neighbour] -= 1;
+                }
+                if !is_pending[neighbour] {
+                    is_pending[neighbour] = true;
+                    pending.push(neighbour);
+                }
+            }
+            if !is_pending[seat] {
+                is_pending[seat] = true;
+                pending.push(seat);
+            }
+        }
+        self.pending = pending;
+
+        changed.len()
+    }
+
+    fn state(&self) -> State {
+        let (rows, columns) = self.graph.shape;
+        let mut tiles = vec![vec![TileState::Floor; columns]; rows];
+        for (seat, &(i, j)) in self.graph.seats.iter().enumerate() {
+            tiles[i][j] = if self.occupied[seat] {
+                TileState::Occupied
+            } else {
+                TileState::Empty
+            };
+        }
+        State(tiles)
+    }
+}
+
+
+fn simulate(start_state: State, rules: &SeatingRules) -> usize {
     let mut last = start_state;
-    let mut current = last.evolve();
+    let mut current = last.evolve(rules);
 
     // let mut iterations = 1;
     while last != current {
         // println!("\nState  {}:\n{}", iterations, current);
         last = current;
-        current = last.evolve();
+        current = last.evolve(rules);
         // iterations += 1;
     }
     // println!("\nFinal state:\n{}", current);
@@ -235,21 +449,276 @@ fn part1(start_state: State) -> usize {
     current.count(TileState::Occupied)
 }
 
+const DEFAULT_ROUND_LIMIT: usize = 10_000;
+
+#[derive(Debug, PartialEq)]
+enum SimulationOutcome {
+    /// No seat changes after `rounds` rounds.
+    Stable { rounds: usize },
+    /// The state after `start_round` rounds repeats every `period` rounds.
+    Cycle { start_round: usize, period: usize },
+    RoundLimit { rounds: usize },
+}
+
+/// Runs the simulation until it becomes stable, enters a cycle or exceeds
+/// `max_rounds`. Returns the final state and how the simulation ended.
+fn simulate_incremental(start_state: State, rules: &SeatingRules, max_rounds: usize)
+    -> (State, SimulationOutcome)
+{
+    simulate_observed(start_state, rules, max_rounds, |_| {})
+}
+
+/// Like `simulate_incremental`, but calls `observe` with the start state and
+/// the state after every round which changed a seat.
+fn simulate_observed<F>(
+    start_state: State,
+    rules: &SeatingRules,
+    max_rounds: usize,
+    mut observe: F,
+) -> (State, SimulationOutcome)
+where
+    F: FnMut(&SeatSimulation),
+{
+    let graph = SeatGraph::new(&start_state, rules);
+    let mut simulation = SeatSimulation::new(&graph, &start_state, rules);
+    observe(&simulation);
+
+    let mut seen: HashMap<Vec<bool>, usize> = HashMap::new();
+    seen.insert(simulation.occupied.clone(), 0);
+    for round in 1..=max_rounds {
+        let changed = simulation.step();
+        if changed > 0 {
+            observe(&simulation);
+        }
+        if changed == 0 {
+            let outcome = SimulationOutcome::Stable {rounds: round - 1};
+            return (simulation.state(), outcome);
+        }
+        if let Some(start_round) = seen.insert(simulation.occupied.clone(), round) {
+            let outcome = SimulationOutcome::Cycle {start_round, period: round - start_round};
+            return (simulation.state(), outcome);
+        }
+    }
+
+    (simulation.state(), SimulationOutcome::RoundLimit {rounds: max_rounds})
+}
+
+fn count_stable_occupied(start_state: State, rules: &SeatingRules) -> usize {
+    match simulate_incremental(start_state, rules, DEFAULT_ROUND_LIMIT) {
+        (state, SimulationOutcome::Stable {..}) => state.count(TileState::Occupied),
+        (_, outcome) => panic!("Seating does not stabilise: {:?}", outcome),
+    }
+}
+
+fn part1(start_state: State) -> usize {
+    count_stable_occupied(start_state, &SeatingRules::adjacent())
+}
+
 fn part2(start_state: State) -> usize {
-    let mut last = start_state;
-    let mut current = last.evolve2();
+    count_stable_occupied(start_state, &SeatingRules::visible())
+}
 
-    // let mut iterations = 1;
-    while last != current {
-    // for _ in 0..=1 {
-        // println!("\nState  {}:\n{}", iterations, current);
-        last = current;
-        current = last.evolve2();
-        // iterations += 1;
+fn benchmark(filename: &str) {
+    for rules in &[SeatingRules::adjacent(), SeatingRules::visible()] {
+        let start = Instant::now();
+        let occupied = simulate(load_state(filename), rules);
+        let full = start.elapsed();
+
+        let start = Instant::now();
+        let incremental = count_stable_occupied(load_state(filename), rules);
+        let fast = start.elapsed();
+
+        assert_eq!(occupied, incremental);
+        println!("{:?}: evolve {:?}, incremental {:?}", rules.neighbourhood, full, fast);
     }
-    // println!("\nFinal state:\n{}", current);
+}
 
-    current.count(TileState::Occupied)
+/// All states from the start state until the simulation stops.
+fn generations(start_state: State, rules: &SeatingRules, max_rounds: usize) -> Vec<State> {
+    let mut frames = Vec::new();
+    simulate_observed(start_state, rules, max_rounds, |simulation| {
+        frames.push(simulation.state());
+    });
+    frames
+}
+
+fn render_ansi(state: &State) -> String {
+    let mut text = String::new();
+    for row in &state.0 {
+        for tile in row {
+            text.push_str(match tile {
+                TileState::Empty => "\x1b[32mL",
+                TileState::Occupied => "\x1b[1;31m#",
+                TileState::Floor => "\x1b[2;37m.",
+            });
+            text.push_str("\x1b[0m");
+        }
+        text.push('\n');
+    }
+    text
+}
+
+fn play(frames: &[State], delay: Duration) -> io::Result<()> {
+    let stdout = io::stdout();
+    let mut out = stdout.lock();
+    for (round, frame) in frames.iter().enumerate() {
+        write!(out, "\x1b[2J\x1b[H{}\nRound {}, occupied seats: {}\n",
+            render_ansi(frame), round, frame.count(TileState::Occupied))?;
+        out.flush()?;
+        thread::sleep(delay);
+    }
+    Ok(())
+}
+
+/// Writes every generation to `<directory>/frame_<round>.txt`.
+fn export_frames(frames: &[State], directory: &str) -> io::Result<()> {
+    std::fs::create_dir_all(directory)?;
+    for (round, frame) in frames.iter().enumerate() {
+        let path = Path::new(directory).join(format!("frame_{:04}.txt", round));
+        std::fs::write(path, format!("{}\n", frame))?;
+    }
+    Ok(())
+}
+
+
+/// Packs variable length codes into bytes, least significant bit first.
+struct BitWriter {
+    bytes: Vec<u8>,
+    buffer: u32,
+    bits: u32,
+}
+
+impl BitWriter {
+    fn write(&mut self, code: u16, size: u32) {
+        self.buffer |= (code as u32) << self.bits;
+        self.bits += size;
+        while self.bits >= 8 {
+            self.bytes.push(self.buffer as u8);
+            self.buffer >>= 8;
+            self.bits -= 8;
+        }
+    }
+
+    fn finish(mut self) -> Vec<u8> {
+        if self.bits > 0 {
+            self.bytes.push(self.buffer as u8);
+        }
+        self.bytes
+    }
+}
+
+fn lzw_encode(min_code_size: u32, pixels: &[u8]) -> Vec<u8> {
+    let clear: u16 = 1 << min_code_size;
+    let end: u16 = clear + 1;
+
+    let mut writer = BitWriter {bytes: Vec::new(), buffer: 0, bits: 0};
+    let mut dictionary: HashMap<(u16, u8), u16> = HashMap::new();
+    let mut next = end + 1;
+    let mut size = min_code_size + 1;
+
+    writer.write(clear, size);
+    let mut pixels = pixels.iter();
+    let mut prefix = match pixels.next() {
+        Some(&pixel) => pixel as u16,
+        None => {
+            writer.write(end, size);
+            return writer.finish();
+        },
+    };
+
+    for &pixel in pixels {
+        if let Some(&code) = dictionary.get(&(prefix, pixel)) {
+            prefix = code;
+            continue;
+        }
+
+        writer.write(prefix, size);
+        if next < 4096 {
+            dictionary.insert((prefix, pixel), next);
+            next += 1;
+            if next > (1 << size) && size < 12 {
+                size += 1;
+            }
+        }
+        else {
+            writer.write(clear, size);
+            dictionary.clear();
+            next = end + 1;
+            size = min_code_size + 1;
+        }
+        prefix = pixel as u16;
+    }
+    writer.write(prefix, size);
+    if next == (1 << size) && size < 12 {
+        size += 1;
+    }
+    writer.write(end, size);
+
+    writer.finish()
+}
+
+/// Encodes the generations as animated GIF, drawing every tile as a square
+/// of `scale` pixels and showing every frame for `delay` hundredths of a
+/// second.
+fn encode_gif(frames: &[State], scale: usize, delay: u16) -> Vec<u8> {
+    let rows = frames.first().map_or(0, |f| f.0.len());
+    let columns = frames.first().and_then(|f| f.0.first()).map_or(0, |r| r.len());
+    let width = (columns * scale) as u16;
+    let height = (rows * scale) as u16;
+
+    let mut gif: Vec<u8> = Vec::new();
+    gif.extend_from_slice(b"GIF89a");
+    gif.extend_from_slice(&width.to_le_bytes());
+    gif.extend_from_slice(&height.to_le_bytes());
+    // Global color table with 4 entries: floor, empty, occupied, unused.
+    gif.extend_from_slice(&[0b1000_0001, 0, 0]);
+    gif.extend_from_slice(&[
+        0x20, 0x20, 0x20,
+        0x2e, 0xa0, 0x43,
+        0xd0, 0x31, 0x2e,
+        0x00, 0x00, 0x00,
+    ]);
+    // Loop forever.
+    gif.extend_from_slice(b"\x21\xff\x0bNETSCAPE2.0\x03\x01\x00\x00\x00");
+
+    for frame in frames {
+        gif.extend_from_slice(&[0x21, 0xf9, 0x04, 0x00]);
+        gif.extend_from_slice(&delay.to_le_bytes());
+        gif.extend_from_slice(&[0x00, 0x00]);
+
+        gif.push(0x2c);
+        gif.extend_from_slice(&[0, 0, 0, 0]);
+        gif.extend_from_slice(&width.to_le_bytes());
+        gif.extend_from_slice(&height.to_le_bytes());
+        gif.push(0);
+
+        let mut pixels: Vec<u8> = Vec::with_capacity(width as usize * height as usize);
+        for row in &frame.0 {
+            let line: Vec<u8> = row.iter()
+                .flat_map(|tile| {
+                    let color = match tile {
+                        TileState::Floor => 0,
+                        TileState::Empty => 1,
+                        TileState::Occupied => 2,
+                    };
+                    std::iter::repeat_n(color, scale)
+                })
+                .collect();
+            for _ in 0..scale {
+                pixels.extend_from_slice(&line);
+            }
+        }
+
+        gif.push(2);
+        for block in lzw_encode(2, &pixels).chunks(255) {
+            gif.push(block.len() as u8);
+            gif.extend_from_slice(block);
+        }
+        gif.push(0);
+    }
+    gif.push(0x3b);
+
+    gif
 }
 
 
@@ -257,6 +726,56 @@ fn main() {
     let filename = "inputs/11.txt";
     // let filename = "test_inputs/11_01.txt";
 
+    // Usage: day11 [play|frames <directory>|gif <file>]
+    //             [adjacent|visible] [leave <n>] [sit <n>] [distance <n>]
+    //             [rounds <n>] [delay <ms>] [scale <pixels>]
+    let mut args: Vec<String> = std::env::args().skip(1).collect();
+    let mut take_option = |name: &str| -> Option<usize> {
+        let i = args.iter().position(|arg| arg == name)?;
+        let option: Vec<String> = args.drain(i..(i + 2).min(args.len())).collect();
+        let value = option.get(1).and_then(|n| n.parse().ok());
+        Some(value.unwrap_or_else(|| panic!("Invalid value for {}.", name)))
+    };
+    let max_rounds = take_option("rounds").unwrap_or(DEFAULT_ROUND_LIMIT);
+    let delay = take_option("delay").unwrap_or(200);
+    let scale = take_option("scale").unwrap_or(4);
+
+    match args.first().map(|s| s.as_str()) {
+        Some("bench") => {
+            benchmark(filename);
+            return;
+        },
+        Some(command @ "play") | Some(command @ "frames") | Some(command @ "gif") => {
+            let command = String::from(command);
+            let target = match command.as_str() {
+                "play" => None,
+                _ => Some(args.get(1).cloned().expect("Missing output path.")),
+            };
+            let skip = if target.is_some() {2} else {1};
+            let rules = SeatingRules::from_args(&args[skip.min(args.len())..])
+                .expect("Invalid seating rules.");
+            let frames = generations(load_state(filename), &rules, max_rounds);
+
+            let result = match (command.as_str(), target) {
+                ("frames", Some(directory)) => export_frames(&frames, &directory),
+                ("gif", Some(file)) => std::fs::write(
+                    file, encode_gif(&frames, scale, (delay / 10) as u16)),
+                _ => play(&frames, Duration::from_millis(delay as u64)),
+            };
+            result.expect("Cannot write generations.");
+            return;
+        },
+        _ => {},
+    }
+    if !args.is_empty() {
+        let rules = SeatingRules::from_args(&args).expect("Invalid seating rules.");
+        let state = load_state(filename);
+        let (state, outcome) = simulate_incremental(state, &rules, max_rounds);
+        println!("{}\n\n{:?}: {:?}, occupied seats: {}",
+            state, rules, outcome, state.count(TileState::Occupied));
+        return;
+    }
+
     let state = load_state(filename);
     println!("Part1: Occpuied seats: {}", part1(state));
 
@@ -275,7 +794,7 @@ mod tests11 {
         let mut state = load_state(filename);
 
         for i in 0..=4 {
-            state = state.evolve();
+            state = state.evolve(&SeatingRules::adjacent());
 
             let filename = format!("test_inputs/11_01_{:02}.txt", i);
             let expectation = load_state(&filename);
@@ -299,7 +818,7 @@ mod tests11 {
             ("04", (3, 3), 0),
         ].iter() {
             let state = load_state(&format!("test_inputs/11_{}.txt", filename));
-            assert_eq!(state.count_visible_occupied_seats(row, col), occ_seats);
+            assert_eq!(state.count_visible_occupied_seats(row, col, None), occ_seats);
 
         }
     }
@@ -310,7 +829,7 @@ mod tests11 {
         let mut state = load_state(filename);
 
         for i in 1..=6 {
-            state = state.evolve2();
+            state = state.evolve(&SeatingRules::visible());
 
             let filename = format!("test_inputs/11_05_{:02}.txt", i);
             let expectation = load_state(&filename);
@@ -318,4 +837,142 @@ mod tests11 {
             assert_eq!(expectation, state);
         }
     }
+
+    #[test]
+    fn test05() {
+        let args: Vec<String> = ["visible", "distance", "1", "leave", "4"]
+            .iter().map(|s| s.to_string()).collect();
+        let rules = SeatingRules::from_args(&args).unwrap();
+        assert_eq!(rules, SeatingRules {
+            neighbourhood: Neighbourhood::Visible,
+            leave_threshold: 4,
+            sit_threshold: 0,
+            max_distance: Some(1),
+        });
+        assert_eq!(SeatingRules::from_args(&["sit".to_string()]), None);
+
+        let from_args = |args: &[&str]| {
+            let args: Vec<String> = args.iter().map(|s| s.to_string()).collect();
+            SeatingRules::from_args(&args).unwrap()
+        };
+        assert_eq!(from_args(&["leave", "4", "visible", "distance", "1"]), rules);
+        assert_eq!(from_args(&["leave", "3", "visible"]).leave_threshold, 3);
+        assert_eq!(from_args(&["visible", "leave", "3"]).leave_threshold, 3);
+        assert_eq!(from_args(&["visible", "adjacent"]), SeatingRules::adjacent());
+        assert_eq!(from_args(&["adjacent", "visible"]), SeatingRules::visible());
+
+        // Looking only one seat far equals the adjacent neighbourhood.
+        let filename = "test_inputs/11_01.txt";
+        let mut adjacent = load_state(filename);
+        let mut visible = load_state(filename);
+        for _ in 0..5 {
+            adjacent = adjacent.evolve(&SeatingRules::adjacent());
+            visible = visible.evolve(&rules);
+            assert_eq!(adjacent, visible);
+        }
+
+        let state = load_state("test_inputs/11_02.txt");
+        assert_eq!(state.count_visible_occupied_seats(4, 3, Some(2)), 3);
+        assert_eq!(simulate(load_state(filename), &SeatingRules::visible()), 26);
+    }
+
+    #[test]
+    fn test06() {
+        let filename = "test_inputs/11_05_00.txt";
+        let state = load_state(filename);
+        let rules = SeatingRules::visible();
+        let graph = SeatGraph::new(&state, &rules);
+        let mut simulation = SeatSimulation::new(&graph, &state, &rules);
+
+        for i in 1..=6 {
+            simulation.step();
+
+            let filename = format!("test_inputs/11_05_{:02}.txt", i);
+            assert_eq!(load_state(&filename), simulation.state());
+        }
+        assert_eq!(simulation.step(), 0);
+        assert_eq!(simulation.state().count(TileState::Occupied), 26);
+
+        let state = load_state("test_inputs/11_02.txt");
+        let graph = SeatGraph::new(&state, &rules);
+        let seat = graph.seats.iter().position(|&s| s == (4, 3)).unwrap();
+        assert_eq!(graph.neighbours[seat].len(), 8);
+    }
+
+    #[test]
+    fn test07() {
+        let filename = "test_inputs/11_01.txt";
+        for rules in &[
+            SeatingRules::adjacent(),
+            SeatingRules::visible(),
+            SeatingRules {max_distance: Some(2), ..SeatingRules::visible()},
+        ] {
+            assert_eq!(
+                count_stable_occupied(load_state(filename), rules),
+                simulate(load_state(filename), rules));
+        }
+    }
+
+    #[test]
+    fn test08() {
+        let filename = "test_inputs/11_01.txt";
+        let (state, outcome) = simulate_incremental(
+            load_state(filename), &SeatingRules::adjacent(), 100);
+        assert_eq!(outcome, SimulationOutcome::Stable {rounds: 5});
+        assert_eq!(state, load_state("test_inputs/11_01_04.txt"));
+
+        let (_, outcome) = simulate_incremental(
+            load_state(filename), &SeatingRules::adjacent(), 3);
+        assert_eq!(outcome, SimulationOutcome::RoundLimit {rounds: 3});
+
+        // Every seat flips in every round.
+        let blinking = SeatingRules {
+            leave_threshold: 0,
+            sit_threshold: 8,
+            ..SeatingRules::adjacent()
+        };
+        let (state, outcome) = simulate_incremental(load_state(filename), &blinking, 100);
+        assert_eq!(outcome, SimulationOutcome::Cycle {start_round: 0, period: 2});
+        assert_eq!(state, load_state(filename));
+
+        // Occupied seats are always left, so the start state does not recur.
+        let delayed = SeatingRules {
+            leave_threshold: 0,
+            ..SeatingRules::adjacent()
+        };
+        let (_, outcome) = simulate_incremental(
+            load_state("test_inputs/11_01_01.txt"), &delayed, 100);
+        assert_eq!(outcome, SimulationOutcome::Cycle {start_round: 1, period: 2});
+    }
+
+    #[test]
+    fn test09() {
+        let filename = "test_inputs/11_01.txt";
+        let frames = generations(load_state(filename), &SeatingRules::adjacent(), 100);
+        assert_eq!(frames.len(), 6);
+        assert_eq!(frames[0], load_state(filename));
+        for i in 0..=4 {
+            let filename = format!("test_inputs/11_01_{:02}.txt", i);
+            assert_eq!(frames[i + 1], load_state(&filename));
+        }
+
+        let text = render_ansi(&frames[1]);
+        assert!(text.starts_with("\x1b[1;31m#\x1b[0m\x1b[2;37m.\x1b[0m"));
+        assert_eq!(text.lines().count(), 10);
+    }
+
+    #[test]
+    fn test10() {
+        let filename = "test_inputs/11_01.txt";
+        let frames = generations(load_state(filename), &SeatingRules::adjacent(), 100);
+        let gif = encode_gif(&frames, 2, 50);
+
+        assert_eq!(&gif[..6], b"GIF89a");
+        assert_eq!(&gif[6..10], &[20, 0, 20, 0]);
+        assert!(gif.iter().filter(|&&b| b == 0x2c).count() >= frames.len());
+        assert_eq!(gif.last(), Some(&0x3b));
+
+        // 0 1 2 3 -> clear, 0, 1, 2, 3, end with 3 bit codes.
+        assert_eq!(lzw_encode(2, &[0, 1, 2, 3]), vec![0x44, 0x34, 0x05]);
+    }
 }
\ No newline at end of file
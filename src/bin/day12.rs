@@ -1,5 +1,7 @@
 use std::fs::File;
 use std::io::{BufRead, BufReader};
+use std::fmt;
+use std::fmt::Write;
 
 #[derive(Debug, Clone, Copy, PartialEq)]
 enum Direction {
@@ -9,30 +11,95 @@ enum Direction {
     East,
 }
 
+#[derive(Debug, Clone, Copy, PartialEq)]
+enum AngleMode {
+    /// Headings stay on the four compass directions, other angles are errors.
+    Lattice,
+    /// Headings and waypoints may point anywhere.
+    Continuous,
+}
+
+#[derive(Debug, PartialEq)]
+enum NavigationErrorKind {
+    InvalidAngle(i32),
+    InvalidInstruction(ActionParseError),
+}
+
+#[derive(Debug, PartialEq)]
+struct NavigationError {
+    kind: NavigationErrorKind,
+}
+
+/// Number of counterclockwise quarter turns for `angle` degrees.
+fn quarter_turns(angle: i32) -> Result<i32, NavigationError> {
+    if angle % 90 != 0 {
+        return Err(NavigationError {
+            kind: NavigationErrorKind::InvalidAngle(angle),
+        });
+    }
+    Ok((angle / 90).rem_euclid(4))
+}
+
+/// Unit vector for a heading in degrees counterclockwise from east, exact
+/// for the compass directions.
+fn unit_vector(degrees: f64) -> (f64, f64) {
+    let degrees = degrees.rem_euclid(360.0);
+    if degrees == 0.0 {
+        (1.0, 0.0)
+    }
+    else if degrees == 90.0 {
+        (0.0, 1.0)
+    }
+    else if degrees == 180.0 {
+        (-1.0, 0.0)
+    }
+    else if degrees == 270.0 {
+        (0.0, -1.0)
+    }
+    else {
+        let radians = degrees.to_radians();
+        (radians.cos(), radians.sin())
+    }
+}
+
+fn rotate_vector(vector: (f64, f64), degrees: f64) -> (f64, f64) {
+    let (cos, sin) = unit_vector(degrees);
+    (vector.0 * cos - vector.1 * sin, vector.0 * sin + vector.1 * cos)
+}
+
+#[derive(Debug, Clone, Copy, PartialEq)]
+struct Distances {
+    manhattan: f64,
+    euclidean: f64,
+}
+
+impl Distances {
+    fn from_origin(position: (f64, f64)) -> Distances {
+        Distances {
+            manhattan: position.0.abs() + position.1.abs(),
+            euclidean: position.0.hypot(position.1),
+        }
+    }
+}
+
 impl Direction {
-    fn turn(&self, a: &Action) -> Direction {
+    fn turn(&self, a: &Action) -> Result<Direction, NavigationError> {
         match a {
             Action::Left(angle) => self.rotate(*angle),
             Action::Right(angle) => self.rotate(-angle),
-            _ => self.clone(),
+            _ => Ok(*self),
         }
     }
 
-    fn rotate(&self, angle: i32) -> Direction {
-        let rotations = angle.abs() / 90;
-        let rotate_right = angle.is_negative();
-
-        let mut direction = *self;
-        for _ in 1..=rotations {
-            if rotate_right {
-                direction = direction.right();
-            }
-            else {
-                direction = direction.left();
-            }
-        }
+    fn rotate(&self, angle: i32) -> Result<Direction, NavigationError> {
+        let direction = match quarter_turns(angle)? {
+            0 => *self,
+            1 => self.left(),
+            2 => self.left().left(),
+            _ => self.right(),
+        };
 
-        direction
+        Ok(direction)
     }
 
     fn left(&self) -> Direction {
@@ -54,7 +121,7 @@ impl Direction {
     }
 }
 
-#[derive(Debug)]
+#[derive(Debug, Clone, Copy, PartialEq)]
 enum Action {
     North(i32),
     South(i32),
@@ -66,14 +133,14 @@ enum Action {
 }
 
 
-#[derive(Debug)]
+#[derive(Debug, PartialEq)]
 enum ActionErrorKind {
     TooShort,
     UnknownAction,
     InvalidDistance,
 }
 
-#[derive(Debug)]
+#[derive(Debug, PartialEq)]
 struct ActionParseError{
     kind: ActionErrorKind,
 }
@@ -118,23 +185,145 @@ impl std::str::FromStr for Action {
     }
 }
 
+impl fmt::Display for Action {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        let (letter, argument) = match self {
+            Action::North(d) => ('N', d),
+            Action::South(d) => ('S', d),
+            Action::West(d) => ('W', d),
+            Action::East(d) => ('E', d),
+            Action::Left(angle) => ('L', angle),
+            Action::Right(angle) => ('R', angle),
+            Action::Forward(d) => ('F', d),
+        };
+        write!(f, "{}{}", letter, argument)
+    }
+}
+
+
+/// Position (and waypoint) of a ship after `action`, which is `None` for the
+/// starting point.
+#[derive(Debug, Clone, PartialEq)]
+struct TrajectoryPoint {
+    action: Option<Action>,
+    position: (i32, i32),
+    waypoint: Option<(i32, i32)>,
+}
+
+#[derive(Debug, Default)]
+struct Trajectory {
+    points: Vec<TrajectoryPoint>,
+}
+
+impl Trajectory {
+    fn iter(&self) -> std::slice::Iter<'_, TrajectoryPoint> {
+        self.points.iter()
+    }
+
+    fn record(&mut self, action: Option<Action>, position: (i32, i32),
+        waypoint: Option<(i32, i32)>)
+    {
+        self.points.push(TrajectoryPoint {action, position, waypoint});
+    }
+
+    fn to_csv(&self) -> String {
+        let mut csv = String::from("step,action,x,y,waypoint_x,waypoint_y\n");
+        for (step, point) in self.iter().enumerate() {
+            let action = point.action.map_or(String::new(), |a| a.to_string());
+            let waypoint = point.waypoint
+                .map_or(String::from(","), |(x, y)| format!("{},{}", x, y));
+            writeln!(csv, "{},{},{},{},{}",
+                step, action, point.position.0, point.position.1, waypoint).unwrap();
+        }
+        csv
+    }
+
+    /// Draws the route as polyline with north pointing up.
+    fn to_svg(&self) -> String {
+        let positions: Vec<(i32, i32)> = self.iter()
+            .map(|p| (p.position.0, -p.position.1))
+            .collect();
+        let min_x = positions.iter().map(|p| p.0).min().unwrap_or(0);
+        let max_x = positions.iter().map(|p| p.0).max().unwrap_or(0);
+        let min_y = positions.iter().map(|p| p.1).min().unwrap_or(0);
+        let max_y = positions.iter().map(|p| p.1).max().unwrap_or(0);
+        let size = (max_x - min_x).max(max_y - min_y).max(1);
+        let margin = size / 20 + 1;
+
+        let points: Vec<String> = positions.iter()
+            .map(|(x, y)| format!("{},{}", x, y))
+            .collect();
+
+        let mut svg = String::new();
+        writeln!(svg, "<svg xmlns=\"http://www.w3.org/2000/svg\" viewBox=\"{} {} {} {}\">",
+            min_x - margin, min_y - margin,
+            max_x - min_x + 2 * margin, max_y - min_y + 2 * margin).unwrap();
+        writeln!(svg, "  <polyline fill=\"none\" stroke=\"navy\" stroke-width=\"{}\" \
+            points=\"{}\"/>", size as f64 / 500.0, points.join(" ")).unwrap();
+        if let (Some(start), Some(end)) = (positions.first(), positions.last()) {
+            for (point, color) in &[(start, "green"), (end, "red")] {
+                writeln!(svg, "  <circle cx=\"{}\" cy=\"{}\" r=\"{}\" fill=\"{}\"/>",
+                    point.0, point.1, size as f64 / 200.0, color).unwrap();
+            }
+        }
+        svg.push_str("</svg>\n");
+        svg
+    }
+}
+
+
+/// A ship model following navigation instructions.
+trait Navigator {
+    fn drive(&mut self, action: &Action) -> Result<(), NavigationError>;
+
+    fn position(&self) -> (f64, f64);
+
+    /// The recorded route, if the model keeps one.
+    fn trajectory(&self) -> Option<&Trajectory> {
+        None
+    }
+
+    fn distances(&self) -> Distances {
+        Distances::from_origin(self.position())
+    }
+}
+
 
 #[derive(Debug)]
 struct Ship {
     direction: Direction,
     position: (i32, i32),
+    trajectory: Trajectory,
 }
 
 impl Ship {
     fn new() -> Ship {
+        let mut trajectory = Trajectory::default();
+        trajectory.record(None, (0, 0), None);
         Ship {
             direction: Direction::East,
             position: (0, 0),
+            trajectory,
         }
     }
 
-    fn drive(&mut self, action: &Action) {
-        let new_direction = self.direction.turn(&action);
+    /// Shortest route back to the origin, facing east.
+    fn return_route(&self) -> Vec<Action> {
+        let position = (self.position.0 as i64, self.position.1 as i64);
+        let mut route = translation(position, (0, 0));
+        match self.direction {
+            Direction::East => {},
+            Direction::North => route.push(Action::Right(90)),
+            Direction::South => route.push(Action::Left(90)),
+            Direction::West => route.push(Action::Left(180)),
+        }
+        route
+    }
+}
+
+impl Navigator for Ship {
+    fn drive(&mut self, action: &Action) -> Result<(), NavigationError> {
+        let new_direction = self.direction.turn(action)?;
         let (move_direction, distance) = match action {
             Action::North(d) => (Direction::North, *d),
             Action::South(d) => (Direction::South, *d),
@@ -153,6 +342,61 @@ impl Ship {
 
         self.direction = new_direction;
         self.position = (self.position.0 + dx, self.position.1 + dy);
+        self.trajectory.record(Some(*action), self.position, None);
+        Ok(())
+    }
+
+    fn position(&self) -> (f64, f64) {
+        (self.position.0 as f64, self.position.1 as f64)
+    }
+
+    fn trajectory(&self) -> Option<&Trajectory> {
+        Some(&self.trajectory)
+    }
+}
+
+/// A ship steering by heading which may turn by any angle.
+#[derive(Debug)]
+struct ContinuousShip {
+    heading: f64,
+    position: (f64, f64),
+}
+
+impl ContinuousShip {
+    fn new() -> ContinuousShip {
+        ContinuousShip {
+            heading: 0.0,
+            position: (0.0, 0.0),
+        }
+    }
+}
+
+impl Navigator for ContinuousShip {
+    fn drive(&mut self, action: &Action) -> Result<(), NavigationError> {
+        let (direction, distance) = match action {
+            Action::North(d) => ((0.0, 1.0), *d),
+            Action::South(d) => ((0.0, -1.0), *d),
+            Action::West(d) => ((-1.0, 0.0), *d),
+            Action::East(d) => ((1.0, 0.0), *d),
+            Action::Left(angle) => {
+                self.heading = (self.heading + *angle as f64).rem_euclid(360.0);
+                return Ok(());
+            },
+            Action::Right(angle) => {
+                self.heading = (self.heading - *angle as f64).rem_euclid(360.0);
+                return Ok(());
+            },
+            Action::Forward(d) => (unit_vector(self.heading), *d),
+        };
+
+        let distance = distance as f64;
+        self.position.0 += direction.0 * distance;
+        self.position.1 += direction.1 * distance;
+        Ok(())
+    }
+
+    fn position(&self) -> (f64, f64) {
+        self.position
     }
 }
 
@@ -177,15 +421,8 @@ fn load_instructions(filename: &str)
 }
 
 fn part1(filename: &str) -> i32 {
-    let instructions = load_instructions(filename)
-        .expect("Cannot load instructions.");
-
     let mut ship = Ship::new();
-    // println!("{:?}", ship);
-    for action in instructions {
-        ship.drive(&action);
-        // println!("{:?} -> {:?}", action, ship);
-    }
+    drive_file(filename, &mut ship).expect("Cannot drive ship.");
 
     ship.position.0.abs() + ship.position.1.abs()
 }
@@ -194,81 +431,649 @@ fn part1(filename: &str) -> i32 {
 struct Ship2 {
     position: (i32, i32),
     waypoint: (i32, i32),
+    trajectory: Trajectory,
 }
 
 impl Ship2 {
     fn new() -> Ship2 {
+        let mut trajectory = Trajectory::default();
+        trajectory.record(None, (0, 0), Some((10, 1)));
         Ship2 {
             position: (0, 0),
             waypoint: (10, 1),
+            trajectory,
         }
     }
 
-    fn rotate_waypoint(&mut self, angle: i32) {
-        let rotations = angle.abs() / 90;
-        let rotate_right = angle.is_negative();
-
+    fn rotate_waypoint(&mut self, angle: i32) -> Result<(), NavigationError> {
         let mut waypoint = self.waypoint;
-        for _ in 1..=rotations {
-            if rotate_right {
-                waypoint = (waypoint.1, -waypoint.0);
+        for _ in 0..quarter_turns(angle)? {
+            waypoint = (-waypoint.1, waypoint.0);
+        }
+        self.waypoint = waypoint;
+        Ok(())
+    }
+
+    /// Shortest route which brings the ship back to the origin and resets
+    /// the waypoint. Routes with several forward moves are searched up to
+    /// the length of the best route with a single one, see `search_route`.
+    fn return_route(&self) -> Vec<Action> {
+        let best = self.single_forward_route();
+        let start = Ship2::new().waypoint;
+        let start = (start.0 as i64, start.1 as i64);
+        let position = (self.position.0 as i64, self.position.1 as i64);
+        let waypoint = (self.waypoint.0 as i64, self.waypoint.1 as i64);
+
+        for limit in 0..best.len() {
+            let mut route = Vec::new();
+            if search_route(position, waypoint, start, limit, &mut route) {
+                return route;
+            }
+        }
+        best
+    }
+
+    /// Shortest route with a single forward move which brings the ship back
+    /// to the origin and resets the waypoint.
+    fn single_forward_route(&self) -> Vec<Action> {
+        let start = Ship2::new().waypoint;
+        let start = (start.0 as i64, start.1 as i64);
+        let current = (self.waypoint.0 as i64, self.waypoint.1 as i64);
+        let (x, y) = (self.position.0 as i64, self.position.1 as i64);
+        if (x, y) == (0, 0) {
+            return waypoint_route(current, start);
+        }
+
+        let gcd = greatest_common_divisor(x.abs(), y.abs());
+        let mut best: Option<Vec<Action>> = None;
+        for times in (1..).take_while(|&k| k <= gcd / k).filter(|k| gcd % k == 0) {
+            for &times in &[times, gcd / times] {
+                let waypoint = (-x / times, -y / times);
+                let mut route = waypoint_route(current, waypoint);
+                push_amount(&mut route, Action::Forward, times);
+                route.extend(waypoint_route(waypoint, start));
+                if best.as_ref().is_none_or(|best| route.len() < best.len()) {
+                    best = Some(route);
+                }
+            }
+        }
+        best.unwrap_or_default()
+    }
+}
+
+fn greatest_common_divisor(a: i64, b: i64) -> i64 {
+    if b == 0 {
+        a
+    }
+    else {
+        greatest_common_divisor(b, a % b)
+    }
+}
+
+/// Appends `action(amount)`, split into several actions if `amount` does not
+/// fit into an `i32`.
+fn push_amount(route: &mut Vec<Action>, action: fn(i32) -> Action, amount: i64) {
+    let mut remaining = amount;
+    while remaining > 0 {
+        let step = remaining.min(i32::MAX as i64);
+        route.push(action(step as i32));
+        remaining -= step;
+    }
+}
+
+/// Moves along the axes changing `from` into `to`.
+fn translation(from: (i64, i64), to: (i64, i64)) -> Vec<Action> {
+    let mut actions = Vec::new();
+    match to.1 - from.1 {
+        0 => {},
+        dy if dy > 0 => push_amount(&mut actions, Action::North, dy),
+        dy => push_amount(&mut actions, Action::South, -dy),
+    }
+    match to.0 - from.0 {
+        0 => {},
+        dx if dx > 0 => push_amount(&mut actions, Action::East, dx),
+        dx => push_amount(&mut actions, Action::West, -dx),
+    }
+    actions
+}
+
+/// Fewest rotations and moves turning waypoint `from` into `to`.
+fn waypoint_route(from: (i64, i64), to: (i64, i64)) -> Vec<Action> {
+    let rotations = [None, Some(Action::Left(90)), Some(Action::Left(180)),
+                     Some(Action::Right(90))];
+    let mut waypoint = from;
+    let mut best: Option<Vec<Action>> = None;
+    for rotation in &rotations {
+        let mut route: Vec<Action> = rotation.iter().copied().collect();
+        route.extend(translation(waypoint, to));
+        if best.as_ref().is_none_or(|best| route.len() < best.len()) {
+            best = Some(route);
+        }
+        waypoint = (-waypoint.1, waypoint.0);
+    }
+    best.unwrap_or_default()
+}
+
+/// Whether a single forward move along `waypoint` reaches the origin.
+fn reaches_origin(position: (i64, i64), waypoint: (i64, i64)) -> bool {
+    let times = if waypoint.0 != 0 {
+        -position.0 / waypoint.0
+    }
+    else if waypoint.1 != 0 {
+        -position.1 / waypoint.1
+    }
+    else {
+        return false;
+    };
+    let back = (position.0 + times * waypoint.0, position.1 + times * waypoint.1);
+    times >= 1 && back == (0, 0)
+}
+
+/// Forward counts worth trying: those finishing the route or one of its
+/// coordinates, and those after which a single forward move along a rotated
+/// current or starting waypoint (or one sharing a coordinate with them)
+/// finishes it.
+fn forward_candidates(position: (i64, i64), waypoint: (i64, i64), start: (i64, i64))
+    -> Vec<i64>
+{
+    let rotate = |w: (i64, i64)| (-w.1, w.0);
+    let mut targets = Vec::new();
+    let (mut w, mut s) = (waypoint, start);
+    for _ in 0..4 {
+        targets.push(s);
+        w = rotate(w);
+        if w != waypoint {
+            targets.push(w);
+        }
+        s = rotate(s);
+    }
+
+    // position + times * waypoint = -other * target
+    let mut counts = Vec::new();
+    for target in &targets {
+        let det = waypoint.0 * target.1 - waypoint.1 * target.0;
+        if det != 0 {
+            let times = -position.0 * target.1 + position.1 * target.0;
+            let other = -waypoint.0 * position.1 + waypoint.1 * position.0;
+            if times % det == 0 && other % det == 0 && other / det >= 1 {
+                counts.push(times / det);
+            }
+        }
+    }
+
+    // position[axis] + times * waypoint[axis] = -value
+    let mut values = vec![0];
+    values.extend(targets.iter().flat_map(|t| vec![t.0, t.1]));
+    for &(p, w) in &[(position.0, waypoint.0), (position.1, waypoint.1)] {
+        for value in &values {
+            if w != 0 && (-value - p) % w == 0 {
+                counts.push((-value - p) / w);
+            }
+        }
+    }
+
+    // Two forward moves totalling `total` clear one axis, and changing the
+    // other waypoint coordinate in between lets the last `last` of them
+    // clear the remainder.
+    for &((p, w), (q, v)) in &[
+        ((position.0, waypoint.0), (position.1, waypoint.1)),
+        ((position.1, waypoint.1), (position.0, waypoint.0)),
+    ] {
+        if w != 0 && p % w == 0 && -p / w > 1 {
+            let total = -p / w;
+            let remainder = (q + total * v).abs();
+            let mut last = 1;
+            while last < total && last <= remainder / last {
+                if remainder % last == 0 {
+                    counts.push(total - last);
+                    counts.push(total - remainder / last);
+                }
+                last += 1;
+            }
+        }
+    }
+
+    counts.retain(|&times| times >= 1 && times <= i32::MAX as i64);
+    counts.sort_unstable();
+    counts.dedup();
+    counts
+}
+
+/// Waypoint coordinates worth moving to along one axis: those of the
+/// rotated starting waypoint, zero, and those letting the next forward move
+/// finish the route.
+fn coordinate_candidates(position: i64, current: i64, other_position: i64,
+    other_waypoint: i64, start: (i64, i64)) -> Vec<i64>
+{
+    let mut values = vec![0, start.0, start.1, -start.0, -start.1, -position];
+    if other_waypoint != 0 && other_position % other_waypoint == 0 {
+        let times = -other_position / other_waypoint;
+        if times >= 1 && position % times == 0 {
+            values.push(-position / times);
+        }
+    }
+    values.retain(|&value| value != current && (value - current).abs() <= i32::MAX as i64);
+    values.sort_unstable();
+    values.dedup();
+    values
+}
+
+/// Depth limited search for a route of at most `depth` actions returning the
+/// ship to the origin with waypoint `start`. Actions between two forward
+/// moves are only tried in the order rotation, north/south, east/west, as
+/// every waypoint change can be written that way. Arguments are taken from
+/// `forward_candidates` and `coordinate_candidates`.
+fn search_route(position: (i64, i64), waypoint: (i64, i64), start: (i64, i64),
+    depth: usize, route: &mut Vec<Action>) -> bool
+{
+    if position == (0, 0) {
+        // Any detour with forward moves takes at least three actions, which
+        // is as many as the waypoint can need.
+        let rest = waypoint_route(waypoint, start);
+        if rest.len() <= depth {
+            route.extend(rest);
+            return true;
+        }
+        return false;
+    }
+
+    let lower_bound = if reaches_origin(position, waypoint) && waypoint == start {1} else {2};
+    if depth < lower_bound {
+        return false;
+    }
+
+    // 0: start, 1: after rotation, 2: after north/south,
+    // 3: after east/west, 4: after forward.
+    let stage = match route.last() {
+        None => 0,
+        Some(Action::Left(_)) | Some(Action::Right(_)) => 1,
+        Some(Action::North(_)) | Some(Action::South(_)) => 2,
+        Some(Action::East(_)) | Some(Action::West(_)) => 3,
+        Some(Action::Forward(_)) => 4,
+    };
+    let try_action = |action: Action, position, waypoint, route: &mut Vec<Action>| {
+        route.push(action);
+        if search_route(position, waypoint, start, depth - 1, route) {
+            return true;
+        }
+        route.pop();
+        false
+    };
+
+    if stage != 4 {
+        for times in forward_candidates(position, waypoint, start) {
+            let next = (position.0 + times * waypoint.0, position.1 + times * waypoint.1);
+            if try_action(Action::Forward(times as i32), next, waypoint, route) {
+                return true;
+            }
+        }
+    }
+    if stage == 0 || stage == 4 {
+        let (x, y) = waypoint;
+        for &(rotation, rotated) in &[
+            (Action::Left(90), (-y, x)),
+            (Action::Left(180), (-x, -y)),
+            (Action::Right(90), (y, -x)),
+        ] {
+            if try_action(rotation, position, rotated, route) {
+                return true;
+            }
+        }
+    }
+    if stage <= 1 || stage == 4 {
+        for y in coordinate_candidates(position.1, waypoint.1, position.0, waypoint.0, start) {
+            let action = if y > waypoint.1 {
+                Action::North((y - waypoint.1) as i32)
             }
             else {
-                waypoint = (-waypoint.1, waypoint.0);
+                Action::South((waypoint.1 - y) as i32)
+            };
+            if try_action(action, position, (waypoint.0, y), route) {
+                return true;
+            }
+        }
+    }
+    if stage != 3 {
+        for x in coordinate_candidates(position.0, waypoint.0, position.1, waypoint.1, start) {
+            let action = if x > waypoint.0 {
+                Action::East((x - waypoint.0) as i32)
+            }
+            else {
+                Action::West((waypoint.0 - x) as i32)
+            };
+            if try_action(action, position, (x, waypoint.1), route) {
+                return true;
             }
         }
-        self.waypoint = waypoint;
     }
+    false
+}
 
-    fn drive(&mut self, action: &Action) {
+/// One action per line, as read by `load_instructions`.
+fn format_route(route: &[Action]) -> String {
+    route.iter().map(|action| format!("{}\n", action)).collect()
+}
+
+impl Navigator for Ship2 {
+    fn drive(&mut self, action: &Action) -> Result<(), NavigationError> {
         match action {
             Action::North(d) => self.waypoint.1 += d,
             Action::South(d) => self.waypoint.1 -= d,
             Action::West(d) => self.waypoint.0 -= d,
             Action::East(d) => self.waypoint.0 += d,
-            Action::Left(angle) => self.rotate_waypoint(*angle),
-            Action::Right(angle) => self.rotate_waypoint(-angle),
+            Action::Left(angle) => self.rotate_waypoint(*angle)?,
+            Action::Right(angle) => self.rotate_waypoint(-angle)?,
             Action::Forward(times) => self.position = (
                 self.position.0 + times * self.waypoint.0,
                 self.position.1 + times * self.waypoint.1
             ),
         }
+        self.trajectory.record(Some(*action), self.position, Some(self.waypoint));
+        Ok(())
+    }
+
+    fn position(&self) -> (f64, f64) {
+        (self.position.0 as f64, self.position.1 as f64)
+    }
+
+    fn trajectory(&self) -> Option<&Trajectory> {
+        Some(&self.trajectory)
+    }
+}
+
+/// A ship following a waypoint which may be rotated by any angle.
+#[derive(Debug)]
+struct ContinuousShip2 {
+    position: (f64, f64),
+    waypoint: (f64, f64),
+}
+
+impl ContinuousShip2 {
+    fn new() -> ContinuousShip2 {
+        ContinuousShip2 {
+            position: (0.0, 0.0),
+            waypoint: (10.0, 1.0),
+        }
     }
 }
 
-fn part2(filename: &str) -> i32 {
-    let instructions = load_instructions(filename)
-        .expect("Cannot load instructions.");
+impl Navigator for ContinuousShip2 {
+    fn drive(&mut self, action: &Action) -> Result<(), NavigationError> {
 
-    let mut ship = Ship2::new();
-    // println!("{:?}", ship);
-    for action in instructions {
-        ship.drive(&action);
-        // println!("{:?} -> {:?}", action, ship);
+        match action {
+            Action::North(d) => self.waypoint.1 += *d as f64,
+            Action::South(d) => self.waypoint.1 -= *d as f64,
+            Action::West(d) => self.waypoint.0 -= *d as f64,
+            Action::East(d) => self.waypoint.0 += *d as f64,
+            Action::Left(angle) =>
+                self.waypoint = rotate_vector(self.waypoint, *angle as f64),
+            Action::Right(angle) =>
+                self.waypoint = rotate_vector(self.waypoint, -*angle as f64),
+            Action::Forward(times) => {
+                self.position.0 += *times as f64 * self.waypoint.0;
+                self.position.1 += *times as f64 * self.waypoint.1;
+            },
+        }
+        Ok(())
     }
 
+    fn position(&self) -> (f64, f64) {
+        self.position
+    }
+}
+
+/// A ship which is carried along by a current after every instruction.
+#[derive(Debug)]
+struct DriftingShip {
+    ship: Ship,
+    current: (i32, i32),
+}
+
+impl DriftingShip {
+    fn new(current: (i32, i32)) -> DriftingShip {
+        DriftingShip {
+            ship: Ship::new(),
+            current,
+        }
+    }
+}
+
+impl Navigator for DriftingShip {
+    fn drive(&mut self, action: &Action) -> Result<(), NavigationError> {
+        self.ship.drive(action)?;
+        let ship = &mut self.ship;
+        ship.position = (ship.position.0 + self.current.0, ship.position.1 + self.current.1);
+        if let Some(point) = ship.trajectory.points.last_mut() {
+            point.position = ship.position;
+        }
+        Ok(())
+    }
+
+    fn position(&self) -> (f64, f64) {
+        self.ship.position()
+    }
+
+    fn trajectory(&self) -> Option<&Trajectory> {
+        self.ship.trajectory()
+    }
+}
+
+/// A waypoint ship whose waypoint stays within `range` units on both axes.
+#[derive(Debug)]
+struct TetheredShip2 {
+    ship: Ship2,
+    range: i32,
+}
+
+impl TetheredShip2 {
+    /// `None` for a negative `range`.
+    fn new(range: i32) -> Option<TetheredShip2> {
+        if range < 0 {
+            return None;
+        }
+        let mut ship = Ship2::new();
+        ship.waypoint = (ship.waypoint.0.clamp(-range, range),
+                         ship.waypoint.1.clamp(-range, range));
+        if let Some(point) = ship.trajectory.points.last_mut() {
+            point.waypoint = Some(ship.waypoint);
+        }
+        Some(TetheredShip2 {ship, range})
+    }
+}
+
+impl Navigator for TetheredShip2 {
+    fn drive(&mut self, action: &Action) -> Result<(), NavigationError> {
+        let ship = &mut self.ship;
+        let range = self.range;
+        match action {
+            Action::Forward(_) => ship.drive(action)?,
+            _ => {
+                let position = ship.position;
+                ship.drive(action)?;
+                ship.waypoint = (ship.waypoint.0.clamp(-range, range),
+                                 ship.waypoint.1.clamp(-range, range));
+                ship.position = position;
+                if let Some(point) = ship.trajectory.points.last_mut() {
+                    point.waypoint = Some(ship.waypoint);
+                }
+            },
+        }
+        Ok(())
+    }
+
+    fn position(&self) -> (f64, f64) {
+        self.ship.position()
+    }
+
+    fn trajectory(&self) -> Option<&Trajectory> {
+        self.ship.trajectory()
+    }
+}
+
+fn part2(filename: &str) -> i32 {
+    let mut ship = Ship2::new();
+    drive_file(filename, &mut ship).expect("Cannot drive ship.");
+
     ship.position.0.abs() + ship.position.1.abs()
 }
 
+/// Follows all instructions from `filename` with any ship model.
+fn drive_file(filename: &str, navigator: &mut dyn Navigator) -> Result<(), NavigationError> {
+    let instructions = load_instructions(filename).map_err(|e| NavigationError {
+        kind: NavigationErrorKind::InvalidInstruction(e),
+    })?;
+
+    for action in &instructions {
+        navigator.drive(action)?;
+    }
+    Ok(())
+}
+
+/// The first (`waypoint == false`) or second ship model.
+fn ship_model(mode: AngleMode, waypoint: bool) -> Box<dyn Navigator> {
+    match (mode, waypoint) {
+        (AngleMode::Lattice, false) => Box::new(Ship::new()),
+        (AngleMode::Lattice, true) => Box::new(Ship2::new()),
+        (AngleMode::Continuous, false) => Box::new(ContinuousShip::new()),
+        (AngleMode::Continuous, true) => Box::new(ContinuousShip2::new()),
+    }
+}
+
+/// Parses `ship|waypoint|continuous|continuous-waypoint`,
+/// `drift <dx> <dy>` or `tethered <range>` with a non-negative range.
+fn model_from_args(args: &[String]) -> Option<Box<dyn Navigator>> {
+    let argument = |i: usize| args.get(i).and_then(|s| s.parse::<i32>().ok());
+    let model: Box<dyn Navigator> = match args.first()?.as_str() {
+        "ship" => ship_model(AngleMode::Lattice, false),
+        "waypoint" => ship_model(AngleMode::Lattice, true),
+        "continuous" => ship_model(AngleMode::Continuous, false),
+        "continuous-waypoint" => ship_model(AngleMode::Continuous, true),
+        "drift" => Box::new(DriftingShip::new((argument(1)?, argument(2)?))),
+        "tethered" => Box::new(TetheredShip2::new(argument(1)?)?),
+        _ => return None,
+    };
+    Some(model)
+}
+
+/// Final distances of the first or second ship model after following the
+/// instructions in `filename`.
+fn navigate(filename: &str, mode: AngleMode, waypoint: bool)
+    -> Result<Distances, NavigationError>
+{
+    let mut ship = ship_model(mode, waypoint);
+    drive_file(filename, ship.as_mut())?;
+    Ok(ship.distances())
+}
+
+/// Trajectory of the first or second ship model.
+fn record_trajectory(filename: &str, waypoint: bool) -> Result<Trajectory, NavigationError> {
+    if waypoint {
+        let mut ship = Ship2::new();
+        drive_file(filename, &mut ship)?;
+        Ok(ship.trajectory)
+    }
+    else {
+        let mut ship = Ship::new();
+        drive_file(filename, &mut ship)?;
+        Ok(ship.trajectory)
+    }
+}
+
 fn main() {
-    // let filename = "test_inputs/12_01.txt";
-    let filename = "inputs/12.txt";
+    // Usage: day12 [lattice|continuous [<filename>]]
+    //        day12 csv|svg 1|2 [<output>]
+    //        day12 model <model> [<model arguments>]
+    //        day12 return 1|2
+    let args: Vec<String> = std::env::args().collect();
+
+    if args.get(1).map(|s| s.as_str()) == Some("return") {
+        let filename = "inputs/12.txt";
+        let route = match args.get(2).map(|s| s.as_str()) {
+            Some("1") => {
+                let mut ship = Ship::new();
+                drive_file(filename, &mut ship).expect("Cannot drive ship.");
+                ship.return_route()
+            },
+            Some("2") => {
+                let mut ship = Ship2::new();
+                drive_file(filename, &mut ship).expect("Cannot drive ship.");
+                ship.return_route()
+            },
+            _ => panic!("Expected part 1 or 2."),
+        };
+        print!("{}", format_route(&route));
+        return;
+    }
+
+    if args.get(1).map(|s| s.as_str()) == Some("model") {
+        let mut ship = model_from_args(&args[2..]).expect("Unknown or invalid ship model.");
+        drive_file("inputs/12.txt", ship.as_mut()).expect("Cannot drive ship.");
+        let distances = ship.distances();
+        println!("Manhattan distance: {:.3}, Euclidean distance: {:.3}",
+            distances.manhattan, distances.euclidean);
+        if let Some(trajectory) = ship.trajectory() {
+            println!("Recorded {} positions.", trajectory.points.len());
+        }
+        return;
+    }
+
+    if let Some(format @ "csv") | Some(format @ "svg") = args.get(1).map(|s| s.as_str()) {
+        let waypoint = match args.get(2).map(|s| s.as_str()) {
+            Some("1") => false,
+            Some("2") => true,
+            _ => panic!("Expected part 1 or 2."),
+        };
+        let trajectory = record_trajectory("inputs/12.txt", waypoint)
+            .expect("Cannot drive ship.");
+        let text = match format {
+            "csv" => trajectory.to_csv(),
+            _ => trajectory.to_svg(),
+        };
+        match args.get(3) {
+            Some(output) => std::fs::write(output, text).expect("Cannot write file."),
+            None => print!("{}", text),
+        }
+        return;
+    }
+
+    let filename = args.get(2).map_or("inputs/12.txt", |s| s.as_str());
+
+    let mode = match args.get(1).map(|s| s.as_str()) {
+        Some("lattice") => AngleMode::Lattice,
+        Some("continuous") => AngleMode::Continuous,
+        Some(other) => panic!("Unknown angle mode {}.", other),
+        None => {
+            println!("Part1: Distance from starting position: {}", part1(filename));
+            println!("Part2: Distance from starting position: {}", part2(filename));
+            return;
+        },
+    };
 
-    println!("Part1: Distance from starting position: {}", part1(filename));
-    println!("Part2: Distance from starting position: {}", part2(filename));
+    for (part, waypoint) in &[(1, false), (2, true)] {
+        match navigate(filename, mode, *waypoint) {
+            Ok(distances) => println!(
+                "Part{}: Manhattan distance: {:.3}, Euclidean distance: {:.3}",
+                part, distances.manhattan, distances.euclidean),
+            Err(e) => println!("Part{}: {:?}", part, e.kind),
+        }
+    }
 }
 
 #[cfg(test)]
 mod tests12 {
     use super::*;
+    use std::collections::HashSet;
 
     #[test]
     fn test_direction() {
         let direction = Direction::North;
         assert_eq!(direction.left(), Direction::West);
 
-        assert_eq!(direction.rotate(90), Direction::West);
-        assert_eq!(direction.rotate(-90), Direction::East);
+        assert_eq!(direction.rotate(90), Ok(Direction::West));
+        assert_eq!(direction.rotate(-90), Ok(Direction::East));
+        assert_eq!(direction.rotate(-450), Ok(Direction::East));
+        assert_eq!(direction.rotate(45), Err(NavigationError {
+            kind: NavigationErrorKind::InvalidAngle(45)}));
     }
 
     #[test]
@@ -290,4 +1095,218 @@ mod tests12 {
     fn test04() {
         assert_eq!(part2("test_inputs/12_01.txt"), 286);
     }
-}
\ No newline at end of file
+
+
+    #[test]
+    fn test05() {
+        let filename = "test_inputs/12_01.txt";
+        let distances = navigate(filename, AngleMode::Lattice, false).unwrap();
+        assert_eq!(distances.manhattan, 25.0);
+        assert_eq!(distances.euclidean, 17.0f64.hypot(8.0));
+        assert_eq!(navigate(filename, AngleMode::Continuous, false), Ok(distances));
+
+        let distances = navigate(filename, AngleMode::Continuous, true).unwrap();
+        assert_eq!(distances.manhattan, 286.0);
+        assert_eq!(navigate(filename, AngleMode::Lattice, true), Ok(distances));
+    }
+
+    #[test]
+    fn test06() {
+        let filename = "test_inputs/12_04.txt";
+        let error = Err(NavigationError {kind: NavigationErrorKind::InvalidAngle(45)});
+        assert_eq!(navigate(filename, AngleMode::Lattice, false), error);
+        assert_eq!(navigate(filename, AngleMode::Lattice, true), error);
+
+        let half = 50f64.sqrt();
+        let distances = navigate(filename, AngleMode::Continuous, false).unwrap();
+        assert!((distances.manhattan - (5.0 + 2.0 * half)).abs() < 1e-9);
+        assert!((distances.euclidean - (10.0 + half).hypot(half - 5.0)).abs() < 1e-9);
+
+        // Turning by 45 degrees moves the waypoint to (9, 11) / sqrt(2), the
+        // following 135 degrees to the right to (1, -10).
+        let position = (105.0 + 9.0 * half, 11.0 * half - 40.0);
+        let distances = navigate(filename, AngleMode::Continuous, true).unwrap();
+        assert!((distances.manhattan - (position.0 + position.1)).abs() < 1e-9);
+        assert!((distances.euclidean - position.0.hypot(position.1)).abs() < 1e-9);
+    }
+
+
+    #[test]
+    fn test07() {
+        let trajectory = record_trajectory("test_inputs/12_01.txt", false).unwrap();
+        let positions: Vec<(i32, i32)> = trajectory.iter().map(|p| p.position).collect();
+        assert_eq!(positions, vec![(0, 0), (10, 0), (10, 3), (17, 3), (17, 3), (17, -8)]);
+        assert!(trajectory.iter().all(|p| p.waypoint.is_none()));
+        assert_eq!(trajectory.to_csv().lines().nth(2), Some("1,F10,10,0,,"));
+
+        let trajectory = record_trajectory("test_inputs/12_01.txt", true).unwrap();
+        assert_eq!(trajectory.points.last(), Some(&TrajectoryPoint {
+            action: Some(Action::Forward(11)),
+            position: (214, -72),
+            waypoint: Some((4, -10)),
+        }));
+        assert_eq!(trajectory.to_csv().lines().nth(5), Some("4,R90,170,38,4,-10"));
+
+        let svg = trajectory.to_svg();
+        assert!(svg.starts_with("<svg"));
+        assert!(svg.contains("points=\"0,0 100,-10 100,-10 170,-38 170,-38 214,72\""));
+    }
+
+
+    #[test]
+    fn test08() {
+        let filename = "test_inputs/12_01.txt";
+        let args = |s: &str| -> Vec<String> { s.split(' ').map(String::from).collect() };
+
+        let mut ship = model_from_args(&args("drift 1 -1")).unwrap();
+        drive_file(filename, ship.as_mut()).unwrap();
+        assert_eq!(ship.position(), (22.0, -13.0));
+        let trajectory = ship.trajectory().unwrap();
+        assert_eq!(trajectory.points[1].position, (11, -1));
+
+        let mut ship = model_from_args(&args("tethered 5")).unwrap();
+        drive_file(filename, ship.as_mut()).unwrap();
+        assert_eq!(ship.position(), (129.0, -17.0));
+        let trajectory = ship.trajectory().unwrap();
+        assert!(trajectory.iter().all(|p| p.waypoint.is_some_and(
+            |(x, y)| x.abs() <= 5 && y.abs() <= 5)));
+
+        assert!(model_from_args(&args("tethered")).is_none());
+        assert!(model_from_args(&args("tethered -5")).is_none());
+        assert!(model_from_args(&args("tethered 0")).is_some());
+        assert!(model_from_args(&args("rowboat")).is_none());
+        assert!(TetheredShip2::new(-1).is_none());
+        assert_eq!(TetheredShip2::new(0).unwrap().ship.waypoint, (0, 0));
+    }
+
+
+    #[test]
+    fn test09() {
+        let filename = "test_inputs/12_01.txt";
+
+        let mut ship = Ship::new();
+        drive_file(filename, &mut ship).unwrap();
+        let route = ship.return_route();
+        assert_eq!(format_route(&route), "N8\nW17\nL90\n");
+        for action in format_route(&route).lines() {
+            ship.drive(&action.parse().unwrap()).unwrap();
+        }
+        assert_eq!((ship.position, ship.direction), ((0, 0), Direction::East));
+
+        let mut ship = Ship2::new();
+        drive_file(filename, &mut ship).unwrap();
+        let route = ship.return_route();
+        assert_eq!(route, vec![Action::North(82), Action::West(218), Action::Forward(1),
+            Action::South(71), Action::East(224)]);
+        for action in format_route(&route).lines() {
+            ship.drive(&action.parse().unwrap()).unwrap();
+        }
+        assert_eq!((ship.position, ship.waypoint), ((0, 0), (10, 1)));
+        assert_eq!(ship.return_route(), vec![]);
+    }
+
+    /// Length of the shortest route of at most `max_length` actions back to
+    /// the origin with the starting waypoint, trying only small arguments.
+    fn shortest_small_route(position: (i32, i32), waypoint: (i32, i32), max_length: usize)
+        -> Option<usize>
+    {
+        let mut actions = vec![Action::Left(90), Action::Left(180), Action::Right(90)];
+        for n in 1..=16 {
+            actions.extend(&[Action::North(n), Action::South(n), Action::East(n),
+                Action::West(n)]);
+        }
+        actions.extend((1..=6).map(Action::Forward));
+
+        let goal = ((0, 0), (10, 1));
+        let mut level = vec![(position, waypoint)];
+        let mut seen: HashSet<((i32, i32), (i32, i32))> = level.iter().copied().collect();
+        for length in 0..=max_length {
+            if level.contains(&goal) {
+                return Some(length);
+            }
+            if length == max_length {
+                break;
+            }
+            let mut next = Vec::new();
+            for &((px, py), (wx, wy)) in &level {
+                for action in &actions {
+                    let state = match *action {
+                        Action::North(n) => ((px, py), (wx, wy + n)),
+                        Action::South(n) => ((px, py), (wx, wy - n)),
+                        Action::East(n) => ((px, py), (wx + n, wy)),
+                        Action::West(n) => ((px, py), (wx - n, wy)),
+                        Action::Left(90) => ((px, py), (-wy, wx)),
+                        Action::Left(_) => ((px, py), (-wx, -wy)),
+                        Action::Right(_) => ((px, py), (wy, -wx)),
+                        Action::Forward(k) => ((px + k * wx, py + k * wy), (wx, wy)),
+                    };
+                    if seen.insert(state) {
+                        next.push(state);
+                    }
+                }
+            }
+            level = next;
+        }
+        None
+    }
+
+    #[test]
+    fn test10() {
+        let mut ship = Ship2::new();
+        ship.position = (-30, -3);
+        assert_eq!(ship.return_route(), vec![Action::Forward(3)]);
+
+        ship.waypoint = (1, -10);
+        assert_eq!(ship.return_route(), vec![Action::Left(90), Action::Forward(3)]);
+
+        ship.position = (6, 4);
+        ship.waypoint = (10, 1);
+        assert_eq!(ship.return_route(), vec![Action::South(5), Action::West(16),
+            Action::Forward(1), Action::North(5), Action::East(16)]);
+        assert_eq!(shortest_small_route((6, 4), (10, 1), 4), None);
+
+        // Two forward moves beat the best route with a single one.
+        ship.position = (-30, -4);
+        let route = ship.return_route();
+        assert_eq!(route.len(), 4);
+        assert_eq!(shortest_small_route((-30, -4), (10, 1), 3), None);
+        for action in &route {
+            ship.drive(action).unwrap();
+        }
+        assert_eq!((ship.position, ship.waypoint), ((0, 0), (10, 1)));
+
+        // The waypoint changes between the two forward moves.
+        ship.position = (-12, -9);
+        ship.waypoint = (2, 2);
+        assert_eq!(ship.return_route().len(), 4);
+        assert_eq!(shortest_small_route((-12, -9), (2, 2), 3), None);
+
+        let mut ship = Ship::new();
+        ship.direction = Direction::West;
+        assert_eq!(ship.return_route(), vec![Action::Left(180)]);
+
+        // Neither the position nor the forward count fit into an i32.
+        let mut ship = Ship2::new();
+        ship.position = (i32::MIN, i32::MIN);
+        ship.waypoint = (1, 1);
+        assert_eq!(ship.return_route(),
+            vec![Action::Forward(i32::MAX), Action::Forward(1), Action::East(9)]);
+
+        let mut ship = Ship2::new();
+        ship.position = (2147395600, 0);
+        ship.waypoint = (-1, 0);
+        assert_eq!(ship.return_route().len(), 3);
+    }
+
+    #[test]
+    fn test11() {
+        let filename = "test_inputs/12_05.txt";
+        let error = Err(NavigationError {
+            kind: NavigationErrorKind::InvalidInstruction(ActionParseError {
+                kind: ActionErrorKind::UnknownAction,
+            }),
+        });
+        assert_eq!(navigate(filename, AngleMode::Lattice, false), error);
+        assert_eq!(record_trajectory(filename, true).err(), error.err());
+    }
+}
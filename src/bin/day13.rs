@@ -40,7 +40,7 @@ fn part1(start: i64, busses: &Vec<i64>) -> i64 {
     next_bus * (next_bus_departure - start)
 }
 
-fn eek(n1: i64, n2: i64) -> (i64, i64, i64) {
+fn eek(n1: i128, n2: i128) -> (i128, i128, i128) {
     let mut a = 0;
     let mut b = n1;
     let mut u = 0;
@@ -74,7 +74,6 @@ fn eek(n1: i64, n2: i64) -> (i64, i64, i64) {
 
         // println!("{}, {}, {}, {}, {}, {}, {}", a, b, q, u, s, v, t);
     }
-    println!("{} * {} + {} * {} = {}", u, n1, v, n2, a);
 
     (a, u, v)
 }
@@ -98,37 +97,128 @@ fn load_input2(filename: &str) -> Vec<(i64, i64)> {
     busses
 }
 
-fn part2(busses: &Vec<(i64, i64)>) -> i64 {
-    // use the "Chinesischer Restsatz"
-    let m_ = busses.iter().fold(1i64, |prod, (_, bus)| prod * *bus);
-    println!("M: {}", m_);
-    let mut x: i64 = busses.iter().map(|(i, bus)| {
-        let mi_ = m_ / *bus;
-        let (_ggt, _ri, si) = eek(*bus, mi_);
-        let ei = si * mi_;
-        let ai = *bus - i;
-        println!("Mi: {}", mi_);
-
-        ai * ei
-    }).sum();
-
-    if x < 0 {
-        x += m_ * (x.abs() / m_ + 1);
+#[derive(Debug, Clone, Copy, PartialEq)]
+enum CrtErrorKind {
+    /// The congruences contradict each other.
+    Inconsistent,
+    /// The solution does not fit into an `i128`.
+    Overflow,
+    InvalidModulus(i128),
+}
+
+#[derive(Debug, PartialEq)]
+struct CrtError {
+    kind: CrtErrorKind,
+}
+
+/// Merges `x = r1 (mod m1)` and `x = r2 (mod m2)` into a single congruence
+/// modulo the least common multiple.
+fn merge_congruences((r1, m1): (i128, i128), (r2, m2): (i128, i128))
+    -> Result<(i128, i128), CrtError>
+{
+    let overflow = || CrtError {kind: CrtErrorKind::Overflow};
+
+    let (ggt, u, _v) = eek(m1, m2);
+    if (r2 - r1) % ggt != 0 {
+        return Err(CrtError {kind: CrtErrorKind::Inconsistent});
     }
-    x - m_ * (x / m_)
+
+    let m = m2 / ggt;
+    let lcm = (m1 / ggt).checked_mul(m2).ok_or_else(overflow)?;
+    // m1 * u = ggt (mod m2), so x = r1 + m1 * k with k = (r2 - r1) / ggt * u.
+    let k = ((r2 - r1) / ggt).rem_euclid(m)
+        .checked_mul(u.rem_euclid(m))
+        .ok_or_else(overflow)?
+        .rem_euclid(m);
+    let x = m1.checked_mul(k)
+        .and_then(|x| x.checked_add(r1))
+        .ok_or_else(overflow)?;
+
+    Ok((x.rem_euclid(lcm), lcm))
+}
+
+/// Solves a system of congruences `x = r (mod m)` whose moduli need not be
+/// coprime. Returns the smallest non-negative solution and the period.
+fn solve_congruences(congruences: &[(i128, i128)]) -> Result<(i128, i128), CrtError> {
+    congruences.iter().try_fold((0, 1), |merged, &(r, m)| {
+        if m <= 0 {
+            return Err(CrtError {kind: CrtErrorKind::InvalidModulus(m)});
+        }
+        merge_congruences(merged, (r.rem_euclid(m), m))
+    })
+}
+
+/// Describes every step taken by `solve_congruences`.
+fn explain_congruences(congruences: &[(i128, i128)]) -> Vec<String> {
+    let mut steps = Vec::new();
+    let mut merged = (0, 1);
+    for &(r, m) in congruences {
+        if m <= 0 {
+            steps.push(format!("Invalid modulus {}", m));
+            return steps;
+        }
+        let r = r.rem_euclid(m);
+        let (ggt, u, v) = eek(merged.1, m);
+        steps.push(format!("x = {} (mod {}) and x = {} (mod {})", merged.0, merged.1, r, m));
+        steps.push(format!("  {} * {} + {} * {} = {}", u, merged.1, v, m, ggt));
+        merged = match merge_congruences(merged, (r, m)) {
+            Ok(next) => next,
+            Err(CrtError {kind: CrtErrorKind::Inconsistent}) => {
+                steps.push(format!("  {} does not divide {} - {}: no solution",
+                    ggt, r, merged.0));
+                return steps;
+            },
+            Err(e) => {
+                steps.push(format!("  {:?}", e.kind));
+                return steps;
+            },
+        };
+        steps.push(format!("  => x = {} (mod {})", merged.0, merged.1));
+    }
+    steps
+}
+
+fn congruences(busses: &[(i64, i64)]) -> Vec<(i128, i128)> {
+    busses.iter()
+        .map(|&(i, bus)| (-(i as i128), bus as i128))
+        .collect()
+}
+
+fn part2(busses: &[(i64, i64)]) -> Result<i128, CrtError> {
+    // use the "Chinesischer Restsatz"
+    solve_congruences(&congruences(busses)).map(|(x, _)| x)
 }
 
 
 fn main() {
-    // let filename = "test_inputs/13_01.txt";
-    let filename = "inputs/13.txt";
+    // Usage: day13 [explain] [<filename>]
+    let mut args: Vec<String> = std::env::args().skip(1).collect();
+    let explain = args.first().map(|s| s.as_str()) == Some("explain");
+    if explain {
+        args.remove(0);
+    }
+    let filename = args.first().map_or("inputs/13.txt", |s| s.as_str());
     let (start, busses) = load_input(filename);
 
-    println!("{:?}, {:?}", start, busses);
+    if explain {
+        println!("{:?}, {:?}", start, busses);
+    }
     println!("Part1: {:?}", part1(start, &busses));
 
     let busses = load_input2(filename);
-    println!("Part2: {:?}", part2(&busses));
+    if explain {
+        for step in explain_congruences(&congruences(&busses)) {
+            println!("{}", step);
+        }
+    }
+    match part2(&busses) {
+        Ok(x) => println!("Part2: {:?}", x),
+        Err(e) => match e.kind {
+            CrtErrorKind::Inconsistent => println!("Part2: No solution"),
+            CrtErrorKind::Overflow => println!("Part2: Solution too large"),
+            CrtErrorKind::InvalidModulus(m) => println!("Part2: Invalid bus {}", m),
+        },
+    }
 }
 
 #[cfg(test)]
@@ -144,12 +234,57 @@ mod tests13 {
 
     #[test]
     fn test02() {
-        let results: [i64; 6] = [1068781, 3417, 754018, 779210, 1261476, 1202161486];
+        let results: [i128; 6] = [1068781, 3417, 754018, 779210, 1261476, 1202161486];
         for i in 0..=5 {
             let filename = format!("test_inputs/13_{:02}.txt", i + 1);
             let busses = load_input2(&filename);
             let x = part2(&busses);
-            assert_eq!(x, results[i]);
+            assert_eq!(x, Ok(results[i]));
         }
     }
-}
\ No newline at end of file
+
+    #[test]
+    fn test03() {
+        let inconsistent = Err(CrtError {kind: CrtErrorKind::Inconsistent});
+        let busses = load_input2("test_inputs/13_07.txt");
+        assert_eq!(part2(&busses), inconsistent);
+
+        assert_eq!(part2(&[(0, 6), (2, 4)]), Ok(6));
+        assert_eq!(part2(&[(0, 6), (1, 9), (2, 4)]), inconsistent);
+        assert_eq!(part2(&[(0, 12), (4, 8), (6, 9), (0, 6)]), Ok(12));
+        assert_eq!(solve_congruences(&[(3, 12), (1, 8), (0, 6)]), Err(CrtError {
+            kind: CrtErrorKind::Inconsistent}));
+        assert_eq!(solve_congruences(&[(3, 15), (8, 10), (0, 0)]), Err(CrtError {
+            kind: CrtErrorKind::InvalidModulus(0)}));
+        assert_eq!(solve_congruences(&[(3, 15), (8, 10)]), Ok((18, 30)));
+    }
+
+    #[test]
+    fn test04() {
+        let big = i64::MAX - 24;
+        assert_eq!(part2(&[(0, big), (1, big - 2)]),
+            Ok(big as i128 * (big as i128 - 2) - big as i128 * ((big as i128 - 1) / 2)));
+        assert_eq!(part2(&[(0, big), (1, big - 2), (2, big - 6)]), Err(CrtError {
+            kind: CrtErrorKind::Overflow}));
+
+        let steps = explain_congruences(&congruences(&[(0, big), (1, big - 2), (2, big - 6)]));
+        assert_eq!(steps.last().map(|s| s.as_str()), Some("  Overflow"));
+    }
+
+    #[test]
+    fn test05() {
+        assert_eq!(eek(15, 10), (5, 1, -1));
+
+        let busses = load_input2("test_inputs/13_02.txt");
+        let steps = explain_congruences(&congruences(&busses));
+        assert_eq!(steps.last().map(|s| s.as_str()), Some("  => x = 3417 (mod 4199)"));
+        assert_eq!(steps[..3], [
+            "x = 0 (mod 1) and x = 0 (mod 17)",
+            "  1 * 1 + 0 * 17 = 1",
+            "  => x = 0 (mod 17)",
+        ]);
+
+        let steps = explain_congruences(&[(0, 6), (1, 4)]);
+        assert_eq!(steps.last().map(|s| s.as_str()), Some("  2 does not divide 1 - 0: no solution"));
+    }
+}